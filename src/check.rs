@@ -0,0 +1,225 @@
+///! Detecting drift between elm.json and elm-vendor.json.
+use crate::lockfile::VendoredDriftError;
+use crate::report::Report;
+use crate::resolver::Strategy;
+use crate::shared::*;
+use crate::version::*;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Everything that differs between two elm.json files.
+#[derive(Serialize, Default, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ElmJsonDiff {
+    /// Dependencies only in the new elm.json.
+    pub(crate) added_dependencies: BTreeMap<String, DependencyVersion>,
+    /// Dependencies only in the old elm.json.
+    pub(crate) removed_dependencies: BTreeMap<String, DependencyVersion>,
+    /// Dependencies in both, but with different versions (old, new).
+    pub(crate) changed_dependencies: BTreeMap<String, (DependencyVersion, DependencyVersion)>,
+    /// Indirect dependencies only in the new elm.json.
+    pub(crate) added_indirect_dependencies: BTreeMap<String, SemVer>,
+    /// Indirect dependencies only in the old elm.json.
+    pub(crate) removed_indirect_dependencies: BTreeMap<String, SemVer>,
+    /// Indirect dependencies in both, but with different versions (old, new).
+    pub(crate) changed_indirect_dependencies: BTreeMap<String, (SemVer, SemVer)>,
+    /// Source directories only in the new elm.json.
+    pub(crate) added_source_dirs: Vec<PathBuf>,
+    /// Source directories only in the old elm.json.
+    pub(crate) removed_source_dirs: Vec<PathBuf>,
+    /// The same source directories, but in a different order.
+    pub(crate) reordered_source_dirs: bool,
+    /// Every other top-level field that was added, removed, or changed.
+    pub(crate) changed_fields: BTreeSet<String>,
+}
+
+impl ElmJsonDiff {
+    pub(crate) fn between(old: &NormalizedElmJson, new: &NormalizedElmJson) -> ElmJsonDiff {
+        let mut diff = ElmJsonDiff::default();
+
+        let (added, removed, changed) = diff_versions(&old.dependencies, &new.dependencies);
+        diff.added_dependencies = added;
+        diff.removed_dependencies = removed;
+        diff.changed_dependencies = changed;
+        let (added, removed, changed) =
+            diff_versions(&old.indirect_dependencies, &new.indirect_dependencies);
+        diff.added_indirect_dependencies = added;
+        diff.removed_indirect_dependencies = removed;
+        diff.changed_indirect_dependencies = changed;
+
+        diff.added_source_dirs = new
+            .source_dirs
+            .iter()
+            .filter(|dir| !old.source_dirs.contains(dir))
+            .cloned()
+            .collect();
+        diff.removed_source_dirs = old
+            .source_dirs
+            .iter()
+            .filter(|dir| !new.source_dirs.contains(dir))
+            .cloned()
+            .collect();
+        diff.reordered_source_dirs = diff.added_source_dirs.is_empty()
+            && diff.removed_source_dirs.is_empty()
+            && old.source_dirs != new.source_dirs;
+
+        if old.kind != new.kind {
+            diff.changed_fields.insert("type".to_owned());
+        }
+        for (field, new_value) in new.other_fields.iter() {
+            if old.other_fields.get(field) != Some(new_value) {
+                diff.changed_fields.insert(field.clone());
+            }
+        }
+        for field in old.other_fields.keys() {
+            if !new.other_fields.contains_key(field) {
+                diff.changed_fields.insert(field.clone());
+            }
+        }
+
+        diff
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.added_dependencies.is_empty()
+            && self.removed_dependencies.is_empty()
+            && self.changed_dependencies.is_empty()
+            && self.added_indirect_dependencies.is_empty()
+            && self.removed_indirect_dependencies.is_empty()
+            && self.changed_indirect_dependencies.is_empty()
+            && self.added_source_dirs.is_empty()
+            && self.removed_source_dirs.is_empty()
+            && !self.reordered_source_dirs
+            && self.changed_fields.is_empty()
+    }
+}
+
+/// Which dependencies were added, removed, and changed (old, new) between two lists of them.
+fn diff_versions<V: Clone + PartialEq>(
+    old: &BTreeMap<String, V>,
+    new: &BTreeMap<String, V>,
+) -> (
+    BTreeMap<String, V>,
+    BTreeMap<String, V>,
+    BTreeMap<String, (V, V)>,
+) {
+    let mut added = BTreeMap::new();
+    let mut changed = BTreeMap::new();
+    for (dependency, new_version) in new.iter() {
+        match old.get(dependency) {
+            None => {
+                added.insert(dependency.clone(), new_version.clone());
+            }
+            Some(old_version) if old_version != new_version => {
+                changed.insert(
+                    dependency.clone(),
+                    (old_version.clone(), new_version.clone()),
+                );
+            }
+            Some(_) => {}
+        }
+    }
+    let removed = old
+        .iter()
+        .filter(|(dependency, _)| !new.contains_key(*dependency))
+        .map(|(dependency, version)| (dependency.clone(), version.clone()))
+        .collect();
+
+    (added, removed, changed)
+}
+
+impl Display for ElmJsonDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (dependency, version) in self.added_dependencies.iter() {
+            writeln!(f, "\t+ dependency {} {}", dependency, version)?;
+        }
+        for (dependency, version) in self.removed_dependencies.iter() {
+            writeln!(f, "\t- dependency {} {}", dependency, version)?;
+        }
+        for (dependency, (old, new)) in self.changed_dependencies.iter() {
+            writeln!(f, "\t~ dependency {} {} -> {}", dependency, old, new)?;
+        }
+        for (dependency, version) in self.added_indirect_dependencies.iter() {
+            writeln!(f, "\t+ indirect dependency {} {}", dependency, version)?;
+        }
+        for (dependency, version) in self.removed_indirect_dependencies.iter() {
+            writeln!(f, "\t- indirect dependency {} {}", dependency, version)?;
+        }
+        for (dependency, (old, new)) in self.changed_indirect_dependencies.iter() {
+            writeln!(
+                f,
+                "\t~ indirect dependency {} {} -> {}",
+                dependency, old, new
+            )?;
+        }
+        for dir in self.added_source_dirs.iter() {
+            writeln!(f, "\t+ source directory {}", dir.display())?;
+        }
+        for dir in self.removed_source_dirs.iter() {
+            writeln!(f, "\t- source directory {}", dir.display())?;
+        }
+        if self.reordered_source_dirs {
+            writeln!(f, "\t~ source directories were reordered")?;
+        }
+        for field in self.changed_fields.iter() {
+            writeln!(f, "\t~ field {}", field)?;
+        }
+        Ok(())
+    }
+}
+
+/// elm.json isn't what vendoring elm-vendor.json would produce.
+#[derive(Error, Debug)]
+pub(crate) struct DriftError(pub(crate) ElmJsonDiff);
+
+impl Display for DriftError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "elm.json has drifted from elm-vendor.json (+ only in elm.json, - only in elm-vendor.json):"
+        )?;
+        write!(f, "{}", self.0)?;
+        writeln!(
+            f,
+            "Use elm-vendor install for new dependencies, and edit elm-vendor.json instead of elm.json."
+        )
+    }
+}
+
+impl super::CmdContext {
+    /// Make sure elm.json is what `vendor` would produce with the versions in elm-vendor.lock,
+    /// wherever they still work, and that no vendored package changed since it was written.
+    ///
+    /// Without a lock file, nothing says elm.json's versions were picked on purpose,
+    /// so they have to be the newest that work.
+    pub fn check(&self) -> Result<Report> {
+        let elm_vendor_json = self.read_elm_vendor()?;
+        let lockfile = self.read_lockfile()?;
+        // whichever strategy vendor used, what it picked is fine as long as it still works
+        let strategy = match lockfile {
+            Some(_) => Strategy::Locked,
+            None => Strategy::Newest,
+        };
+        let (expected, _, _) =
+            self.merge_vendored(&elm_vendor_json, &elm_vendor_json.vendored, strategy)?;
+        let actual = self.read_elm_json()?;
+
+        let diff = ElmJsonDiff::between(&expected, &actual);
+        if !diff.is_empty() {
+            return Err(DriftError(diff).into());
+        }
+
+        if let Some(lockfile) = lockfile {
+            let changed = self.changed_vendored(&lockfile, &elm_vendor_json.vendored)?;
+            if !changed.is_empty() {
+                return Err(VendoredDriftError(changed).into());
+            }
+        }
+
+        Ok(Report::InSync)
+    }
+}
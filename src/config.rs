@@ -0,0 +1,177 @@
+///! Reading and writing the elm-vendor config file,
+///! which can be either elm-vendor.json or elm-vendor.toml.
+use crate::shared::ElmVendor;
+use crate::toml_update::update_document;
+use crate::vendoring::WithDepNameExt;
+use crate::writer::to_elm_style_string;
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use toml_edit::Document;
+
+/// The flavors of config file we understand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Every format, in the order we'd pick them.
+    pub(crate) const ALL: [ConfigFormat; 2] = [ConfigFormat::Json, ConfigFormat::Toml];
+
+    pub(crate) fn file_name(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "elm-vendor.json",
+            ConfigFormat::Toml => "elm-vendor.toml",
+        }
+    }
+
+    /// Which format the given file is in, if it's an elm-vendor config file at all.
+    pub(crate) fn from_file_name(name: &OsStr) -> Option<ConfigFormat> {
+        ConfigFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| name == format.file_name())
+    }
+
+    pub(crate) fn parse(self, contents: &str) -> Result<ElmVendor> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::from_str(contents).with_name(self.file_name())?,
+            ConfigFormat::Toml => toml::from_str(contents).with_name(self.file_name())?,
+        })
+    }
+
+    pub(crate) fn serialize(self, elm_vendor: &ElmVendor) -> Result<String> {
+        Ok(match self {
+            ConfigFormat::Json => to_elm_style_string(elm_vendor)?,
+            ConfigFormat::Toml => to_toml_string(elm_vendor)?,
+        })
+    }
+
+    /// Serialize, but keep as much of the `previous` contents of the file as we can.
+    ///
+    /// For TOML that means comments and layout survive, since people write those by hand.
+    pub(crate) fn update(self, previous: &str, elm_vendor: &ElmVendor) -> Result<String> {
+        let serialized = self.serialize(elm_vendor)?;
+
+        Ok(match self {
+            ConfigFormat::Json => serialized,
+            ConfigFormat::Toml => update_document(previous, &serialized)
+                .with_context(|| format!("Couldn't parse {}", self.file_name()))?,
+        })
+    }
+}
+
+/// Going through `toml::Value` puts plain values before tables, like TOML requires.
+///
+/// The toml crate can't write an array that mixes strings and tables, though,
+/// which the vendored list is when only some entries have details.
+/// toml_edit writes that one instead, with the tables inline.
+fn to_toml_string(elm_vendor: &ElmVendor) -> Result<String> {
+    let mut value = toml::Value::try_from(elm_vendor)?;
+    let is_mixed = match value.get("vendored") {
+        Some(toml::Value::Array(entries)) => {
+            entries.iter().any(toml::Value::is_str) && entries.iter().any(toml::Value::is_table)
+        }
+        _ => false,
+    };
+    if !is_mixed {
+        return Ok(toml::to_string_pretty(&value)?);
+    }
+
+    if let Some(table) = value.as_table_mut() {
+        table.remove("vendored");
+    }
+    let mut document: Document = toml::to_string_pretty(&value)?.parse()?;
+    document["vendored"] = toml_edit::ser::to_item(&elm_vendor.vendored)?;
+    Ok(document.to_string())
+}
+
+impl Display for ConfigFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigFormat::Json => write!(f, "json"),
+            ConfigFormat::Toml => write!(f, "toml"),
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<ConfigFormat, String> {
+        match str {
+            "json" => Ok(ConfigFormat::Json),
+            "toml" => Ok(ConfigFormat::Toml),
+            _ => Err(format!("{} isn't json or toml", str)),
+        }
+    }
+}
+
+impl super::CmdContext {
+    /// Find the elm-vendor config file for this project, and which format it's in.
+    pub(crate) fn find_config(&self) -> Result<(PathBuf, ConfigFormat)> {
+        let file_name = self
+            .find_elm_vendor_json()?
+            // TODO do we just run it for them?
+            .context("you must run elm-vendor init first")?;
+        let format = ConfigFormat::from_file_name(&file_name)
+            .expect("find_elm_vendor_json only finds config files");
+
+        Ok((self.root.join(file_name), format))
+    }
+
+    /// Read the elm-vendor config file for this project.
+    pub(crate) fn read_elm_vendor(&self) -> Result<ElmVendor> {
+        let (path, format) = self.find_config()?;
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        format.parse(&contents)
+    }
+
+    /// Overwrite the elm-vendor config file for this project.
+    pub(crate) fn write_elm_vendor(&self, elm_vendor: &ElmVendor) -> Result<()> {
+        let (path, format) = self.find_config()?;
+
+        let previous = fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        self.write_file(&path, &format.update(&previous, elm_vendor)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::{ElmJsonKind, VendoredPackage};
+
+    #[test]
+    fn test_toml_vendored_list_can_mix_paths_and_details() {
+        let elm_vendor = ElmVendor {
+            main_deps: Default::default(),
+            source_dirs: vec!["src".into()],
+            vendored: vec![
+                VendoredPackage::at("vendor/a"),
+                VendoredPackage {
+                    notes: Some("patched".to_owned()),
+                    ..VendoredPackage::at("vendor/b")
+                },
+            ],
+            overrides: Default::default(),
+            pre_vendor_elm_json: None,
+            kind: ElmJsonKind::Application,
+            extras: Default::default(),
+        };
+
+        let toml = ConfigFormat::Toml.serialize(&elm_vendor).unwrap();
+        assert!(
+            toml.contains(r#"vendored = ["vendor/a", { path = "vendor/b", notes = "patched" }]"#)
+        );
+        let parsed = ConfigFormat::Toml.parse(&toml).unwrap();
+        assert_eq!(parsed.vendored, elm_vendor.vendored);
+    }
+}
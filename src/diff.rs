@@ -0,0 +1,55 @@
+///! Showing how a file would change, for dry runs.
+use console::Style;
+use similar::{ChangeTag, TextDiff};
+use std::fmt::Write;
+
+/// A unified diff between two versions of the named file,
+/// colored if stderr (where dry runs show it) is a terminal.
+///
+/// Empty if nothing changed.
+pub(crate) fn unified_diff(name: &str, old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let unified = diff.unified_diff();
+    let mut out = String::new();
+
+    // dry runs show diffs on stderr, so that's what decides whether they're colored
+    let style = || Style::new().for_stderr();
+    for (index, hunk) in unified.iter_hunks().enumerate() {
+        if index == 0 {
+            let bold = style().bold();
+            writeln!(out, "{}", bold.apply_to(format!("--- a/{}", name))).unwrap();
+            writeln!(out, "{}", bold.apply_to(format!("+++ b/{}", name))).unwrap();
+        }
+        writeln!(out, "{}", style().cyan().apply_to(hunk.header())).unwrap();
+
+        for change in hunk.iter_changes() {
+            let line = format!("{}{}", change.tag(), change.value().trim_end_matches('\n'));
+            let line = match change.tag() {
+                ChangeTag::Delete => style().red().apply_to(line),
+                ChangeTag::Insert => style().green().apply_to(line),
+                ChangeTag::Equal => style().apply_to(line),
+            };
+            writeln!(out, "{}", line).unwrap();
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        console::set_colors_enabled_stderr(false);
+        let old = "{\n    \"a\": 1,\n    \"b\": 2\n}\n";
+        let new = "{\n    \"a\": 1,\n    \"b\": 3\n}\n";
+
+        assert_eq!(
+            unified_diff("elm.json", old, new),
+            "--- a/elm.json\n+++ b/elm.json\n@@ -1,4 +1,4 @@\n {\n     \"a\": 1,\n-    \"b\": 2\n+    \"b\": 3\n }\n"
+        );
+        assert_eq!(unified_diff("elm.json", old, old), "");
+    }
+}
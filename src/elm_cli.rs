@@ -1,54 +1,98 @@
 ///! Helpers for executing `elm`/`lamdera`.
-use anyhow::Result;
-use crate::utils::*;
-#[allow(unused_imports)]
-use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::{digit1, space0},
-    combinator::{map, map_res},
-    error::ParseError,
-    sequence::{delimited, tuple},
-    Finish, IResult,
-};
+use anyhow::{bail, Context, Result};
 use std::ffi::OsStr;
-#[allow(unused_imports)]
-use std::process::{self, Child, ChildStdin, ChildStdout, Command, ExitStatus, Output, Stdio};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
 
-fn elm_binary(is_lamdera: bool) -> Command {
-    Command::new(if is_lamdera { "lamdera" } else { "elm" })
+/// Which binary to run: lamdera projects use lamdera's own elm.
+fn elm_binary(is_lamdera: bool) -> &'static str {
+    if is_lamdera {
+        "lamdera"
+    } else {
+        "elm"
+    }
 }
 
-fn elm_install(is_lamdera: bool, dependency: impl AsRef<OsStr>) -> Result<()> {
-    let mut cmd = elm_binary(is_lamdera);
-    cmd.arg("install")
-        .arg(dependency)
+/// Run `elm install` (or `lamdera install`) in the given project.
+///
+/// elm explains what it's going to do and then asks for permission.
+/// That explanation is passed to `approve`, which decides how to answer.
+///
+/// Returns whether elm.json was (possibly) changed.
+pub(crate) fn elm_install(
+    is_lamdera: bool,
+    root: &Path,
+    dependency: impl AsRef<OsStr>,
+    approve: impl FnOnce(&str) -> Result<bool>,
+) -> Result<bool> {
+    let binary = elm_binary(is_lamdera);
+    let mut cmd = Command::new(binary);
+    cmd.current_dir(root)
+        .arg("install")
+        .arg(dependency.as_ref())
         .stdin(Stdio::piped())
         .stdout(Stdio::piped());
 
-    let mut child = cmd.spawn()?;
-    let stdin = child.stdin.take().unwrap();
-    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("Couldn't run {}. Is it installed?", binary))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
 
-    let mut line = String::new();
+    let (plan, asked) = read_until_prompt(&mut stdout)?;
 
-    stdout.read_line(&mut line)?;
+    let approved = if asked {
+        let approved = approve(&plan)?;
+        writeln!(stdin, "{}", if approved { "y" } else { "n" })?;
+        approved
+    } else {
+        // Nothing to ask about, e.g. because it was already installed.
+        eprint!("{}", plan);
+        false
+    };
+    drop(stdin);
 
-    here_is_my_plan(&line).finalize()?;
+    let mut rest = String::new();
+    stdout.read_to_string(&mut rest)?;
+    // our own stdout is for the report
+    eprint!("{}", rest);
 
-    loop {
-        stdout.read_line(&mut line)?;
-        if wanna_update(&line).is_ok() {
-            break;
-        }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!(
+            "`{} install {}` failed ({})",
+            binary,
+            dependency.as_ref().to_string_lossy(),
+            status
+        );
     }
 
-    write!(stdin, "\n")?;
-    
-    //TODO: check output / return status 
+    Ok(approved)
+}
+
+/// Read elm's output until it asks a question, or until it exits.
+///
+/// elm doesn't end its question with a newline,
+/// so we can't read line by line.
+///
+/// Returns the output without the question, and whether a question was asked.
+fn read_until_prompt(stdout: &mut impl Read) -> Result<(String, bool)> {
+    let mut output = Vec::new();
+    let mut buf = [0; 1024];
+
+    loop {
+        let read = stdout.read(&mut buf)?;
+        if read == 0 {
+            return Ok((String::from_utf8_lossy(&output).into_owned(), false));
+        }
+        output.extend_from_slice(&buf[..read]);
 
-    todo!()
+        let text = String::from_utf8_lossy(&output);
+        if let Some(plan) = without_prompt(&text) {
+            return Ok((plan.to_owned(), true));
+        }
+    }
 }
 
 // Here is my plan:
@@ -57,13 +101,83 @@ fn elm_install(is_lamdera: bool, dependency: impl AsRef<OsStr>) -> Result<()> {
 
 // Would you like me to update your elm.json accordingly? [Y/n]:
 
-fn here_is_my_plan(input: &str) -> IResult<&str, ()> {
-    map(tag("Here is my plan:\n"), |_| ())(input)
+/// elm ends every yes/no question with this.
+const YES_NO_PROMPT: &str = "[Y/n]:";
+
+/// If elm is waiting for an answer, strip the question off of its output.
+fn without_prompt(output: &str) -> Option<&str> {
+    let output = output.trim_end();
+    if !output.ends_with(YES_NO_PROMPT) {
+        return None;
+    }
+
+    // the question itself is the last line
+    let question_start = output.rfind('\n').map_or(0, |newline| newline + 1);
+    Some(&output[..question_start])
 }
 
-fn wanna_update(input: &str) -> IResult<&str, ()> {
-    map(
-        tag("Would you like me to update your elm.json accordingly? [Y/n]:"),
-        |_| (),
-    )(input)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAN: &str = "Here is my plan:\n  \n  Add:\n    TSFoster/elm-uuid    4.1.0\n\n";
+    const QUESTION: &str = "Would you like me to update your elm.json accordingly? [Y/n]: ";
+
+    /// Hands out its output a byte at a time, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((byte, rest)) if !buf.is_empty() => {
+                    buf[0] = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_is_read_up_to_the_question() {
+        let output = format!("{}{}", PLAN, QUESTION);
+        let (plan, asked) = read_until_prompt(&mut output.as_bytes()).unwrap();
+        assert!(asked);
+        assert_eq!(plan, PLAN);
+    }
+
+    #[test]
+    fn test_question_can_arrive_in_pieces() {
+        let output = format!("{}{}", PLAN, QUESTION);
+        let (plan, asked) = read_until_prompt(&mut Trickle(output.as_bytes())).unwrap();
+        assert!(asked);
+        assert_eq!(plan, PLAN);
+    }
+
+    #[test]
+    fn test_output_without_a_question_is_read_to_the_end() {
+        let output = "It is already installed!\n";
+        let (plan, asked) = read_until_prompt(&mut output.as_bytes()).unwrap();
+        assert!(!asked);
+        assert_eq!(plan, output);
+    }
+
+    #[test]
+    fn test_exiting_halfway_through_the_question_is_not_a_question() {
+        let output = format!(
+            "{}Would you like me to update your elm.json accordingly? [Y/",
+            PLAN
+        );
+        let (plan, asked) = read_until_prompt(&mut output.as_bytes()).unwrap();
+        assert!(!asked);
+        assert_eq!(plan, output);
+    }
+
+    #[test]
+    fn test_without_prompt() {
+        assert_eq!(without_prompt("Here is my plan:\n"), None);
+        assert_eq!(without_prompt(QUESTION), Some(""));
+        assert_eq!(without_prompt(&format!("{}{}", PLAN, QUESTION)), Some(PLAN));
+    }
 }
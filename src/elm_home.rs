@@ -0,0 +1,261 @@
+///! Reading what elm has already cached in ELM_HOME (usually `~/.elm`),
+///! so we can work without a network connection.
+use crate::resolver::AvailableVersions;
+use crate::shared::PackageJson;
+use crate::version::{DependencyVersion, SemVer};
+use anyhow::{anyhow, Context, Result};
+use nom::{
+    combinator::{all_consuming, map, map_res},
+    multi::{length_count, length_data},
+    number::complete::{be_u16, be_u64, be_u8},
+    sequence::tuple,
+    Finish, IResult,
+};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The version of the elm compiler whose caches we read.
+const ELM_VERSION: &str = "0.19.1";
+
+/// Where elm keeps its caches, honoring ELM_HOME just like elm does.
+fn default_elm_home() -> Option<PathBuf> {
+    if let Some(elm_home) = env::var_os("ELM_HOME") {
+        return Some(elm_home.into());
+    }
+
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(|app_data| PathBuf::from(app_data).join("elm"))
+    } else {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".elm"))
+    }
+}
+
+impl super::CmdContext {
+    /// Where elm keeps its caches for this project:
+    /// the one we were given, or wherever elm would look.
+    pub(crate) fn elm_home(&self) -> Option<PathBuf> {
+        self.elm_home.clone().or_else(default_elm_home)
+    }
+}
+
+/// Where elm caches downloaded packages, and its copy of the package registry.
+fn packages_dir(elm_home: Option<&Path>) -> Option<PathBuf> {
+    elm_home.map(|elm_home| elm_home.join(ELM_VERSION).join("packages"))
+}
+
+/// Read every known version of every package from elm's cached registry.
+///
+/// Returns `None` if elm hasn't downloaded the registry yet.
+pub(crate) fn read_registry(elm_home: Option<&Path>) -> Result<Option<AvailableVersions>> {
+    let path = match packages_dir(elm_home) {
+        Some(packages_dir) => packages_dir.join("registry.dat"),
+        None => return Ok(None),
+    };
+
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Couldn't read {}", path.display())),
+    };
+
+    parse_registry(&bytes)
+        .map(Some)
+        .with_context(|| format!("Couldn't understand {}", path.display()))
+}
+
+/// Read what a version of a package depends on from elm's package cache.
+///
+/// Returns `None` if elm hasn't downloaded that version.
+pub(crate) fn read_cached_dependencies(
+    elm_home: Option<&Path>,
+    package: &str,
+    version: &SemVer,
+) -> Result<Option<BTreeMap<String, DependencyVersion>>> {
+    let path = match packages_dir(elm_home) {
+        Some(packages_dir) => packages_dir
+            .join(package)
+            .join(version.to_string())
+            .join("elm.json"),
+        None => return Ok(None),
+    };
+
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Couldn't read {}", path.display())),
+    };
+
+    let package_json: PackageJson = serde_json::from_reader(file)
+        .with_context(|| format!("Couldn't understand {}", path.display()))?;
+    Ok(Some(package_json.dependencies))
+}
+
+/// Every version of every package elm has downloaded.
+pub(crate) fn cached_versions(elm_home: Option<&Path>) -> Result<AvailableVersions> {
+    let mut available = AvailableVersions::new();
+
+    let packages_dir = match packages_dir(elm_home) {
+        Some(packages_dir) => packages_dir,
+        None => return Ok(available),
+    };
+    let authors = match fs::read_dir(&packages_dir) {
+        Ok(authors) => authors,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(available),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Couldn't read {}", packages_dir.display()))
+        }
+    };
+
+    for author in authors {
+        let author = author?;
+        if !author.file_type()?.is_dir() {
+            continue;
+        }
+
+        for project in fs::read_dir(author.path())? {
+            let project = project?;
+            if !project.file_type()?.is_dir() {
+                continue;
+            }
+            let package = format!(
+                "{}/{}",
+                author.file_name().to_string_lossy(),
+                project.file_name().to_string_lossy()
+            );
+
+            for version_dir in fs::read_dir(project.path())? {
+                let version_dir = version_dir?;
+                let version = version_dir
+                    .file_name()
+                    .to_str()
+                    .and_then(|version| version.parse::<SemVer>().ok());
+
+                if let Some(version) = version {
+                    if version_dir.path().join("elm.json").is_file() {
+                        available.entry(package.clone()).or_default().push(version);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(available)
+}
+
+//region parsers
+// registry.dat is written with Haskell's Data.Binary:
+// lengths and counts are big-endian 64 bit ints,
+// and names and versions use elm's own compact encodings.
+
+fn parse_registry(bytes: &[u8]) -> Result<AvailableVersions> {
+    all_consuming(registry)(bytes)
+        .finish()
+        .map(|(_, registry)| registry)
+        .map_err(|err| {
+            anyhow!(
+                "unexpected data at byte {} ({:?})",
+                bytes.len() - err.input.len(),
+                err.code
+            )
+        })
+}
+
+/// Every package and its versions.
+/// The leading count is the total number of versions, which we don't need.
+fn registry(input: &[u8]) -> IResult<&[u8], AvailableVersions> {
+    let (input, _count) = be_u64(input)?;
+
+    map(
+        length_count(be_u64, tuple((package_name, known_versions))),
+        |packages| packages.into_iter().collect(),
+    )(input)
+}
+
+/// A string of at most 255 bytes, prefixed with its length.
+fn short_string(input: &[u8]) -> IResult<&[u8], &str> {
+    map_res(length_data(be_u8), std::str::from_utf8)(input)
+}
+
+fn package_name(input: &[u8]) -> IResult<&[u8], String> {
+    map(tuple((short_string, short_string)), |(author, project)| {
+        format!("{}/{}", author, project)
+    })(input)
+}
+
+/// The newest version, followed by a list of every previous one.
+fn known_versions(input: &[u8]) -> IResult<&[u8], Vec<SemVer>> {
+    map(
+        tuple((version, length_count(be_u64, version))),
+        |(newest, mut previous)| {
+            previous.push(newest);
+            previous
+        },
+    )(input)
+}
+
+/// Versions with small numbers are a byte each.
+/// Otherwise, a 255 byte marks three 16 bit numbers.
+fn version(input: &[u8]) -> IResult<&[u8], SemVer> {
+    let (input, major) = be_u8(input)?;
+
+    if major == 255 {
+        map(tuple((be_u16, be_u16, be_u16)), |(major, minor, patch)| {
+            SemVer {
+                major: major.into(),
+                minor: minor.into(),
+                patch: patch.into(),
+            }
+        })(input)
+    } else {
+        map(tuple((be_u8, be_u8)), |(minor, patch)| SemVer {
+            major: major.into(),
+            minor: minor.into(),
+            patch: patch.into(),
+        })(input)
+    }
+}
+//endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_short_string(bytes: &mut Vec<u8>, string: &str) {
+        bytes.push(string.len() as u8);
+        bytes.extend_from_slice(string.as_bytes());
+    }
+
+    #[test]
+    fn test_parse_registry() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&3u64.to_be_bytes());
+        bytes.extend_from_slice(&2u64.to_be_bytes());
+
+        push_short_string(&mut bytes, "elm");
+        push_short_string(&mut bytes, "core");
+        bytes.extend_from_slice(&[1, 0, 5]);
+        bytes.extend_from_slice(&1u64.to_be_bytes());
+        bytes.extend_from_slice(&[1, 0, 0]);
+
+        push_short_string(&mut bytes, "elm");
+        push_short_string(&mut bytes, "json");
+        bytes.extend_from_slice(&[255, 0, 1, 1, 0, 0, 3]);
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+
+        let registry = parse_registry(&bytes).unwrap();
+
+        assert_eq!(
+            registry["elm/core"],
+            vec!["1.0.0".parse().unwrap(), "1.0.5".parse().unwrap()]
+        );
+        assert_eq!(registry["elm/json"], vec!["1.256.3".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_parse_truncated_registry() {
+        assert!(parse_registry(&[0, 0, 0]).is_err());
+    }
+}
@@ -3,41 +3,153 @@ mod utils;
 mod vendoring;
 mod version;
 mod elm_cli;
+mod check;
+mod resolver;
+mod elm_home;
+mod writer;
+mod config;
+mod toml_update;
+mod report;
+mod diff;
+mod lock;
+mod lockfile;
+mod transaction;
 
-use anyhow::Result;
+pub use config::ConfigFormat;
+pub use report::{OutputFormat, Report};
+pub use resolver::Strategy;
+
+use anyhow::{Context, Result};
 use dialoguer::Confirm;
 use git2::{self, Repository};
 use shared::*;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::{OsStr, OsString};
 use std::fs::{self};
-use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 
 pub struct CmdContext {
     pub yes: bool,
     pub root: PathBuf,
+    /// Show how files would change instead of changing them.
+    pub dry_run: bool,
+    /// Where elm keeps its caches, if not where elm itself would look (ELM_HOME or `~/.elm`).
+    pub elm_home: Option<PathBuf>,
+}
+
+/// Find the elm project that `start` is in,
+/// which is the closest directory at or above it with an elm-vendor config file.
+///
+/// Vendored packages have an elm.json of their own, so that isn't enough to go on,
+/// except before `init`, when it's the closest directory with an elm.json instead.
+pub fn find_project_root(start: &Path) -> Result<PathBuf> {
+    let start = start
+        .canonicalize()
+        .with_context(|| format!("Couldn't find the directory {}", start.display()))?;
+    let has_config = |dir: &&Path| {
+        ConfigFormat::ALL
+            .iter()
+            .any(|format| dir.join(format.file_name()).is_file())
+    };
+
+    start
+        .ancestors()
+        .find(has_config)
+        .or_else(|| start.ancestors().find(|dir| dir.join("elm.json").is_file()))
+        .map(Path::to_path_buf)
+        .with_context(|| {
+            format!(
+                "There's no elm.json in {} or any directory above it",
+                start.display()
+            )
+        })
+}
+
+/// Where `dir`, given relative to `start` (like on the command line),
+/// is inside the project at `root`, written the way elm-vendor.json writes it.
+///
+/// `dir` doesn't have to exist, since a vendored package might already be deleted.
+pub fn path_in_project(root: &Path, start: &Path, dir: &str) -> Result<String> {
+    let start = start
+        .canonicalize()
+        .with_context(|| format!("Couldn't find the directory {}", start.display()))?;
+    let joined = start.join(dir);
+    let absolute = joined
+        .canonicalize()
+        .unwrap_or_else(|_| normalize_lexically(&joined));
+    let relative = absolute
+        .strip_prefix(root)
+        .with_context(|| format!("{} isn't inside the project at {}", dir, root.display()))?;
+
+    vendoring::to_vendored_path(relative)
+}
+
+/// Resolve `.` and `..` without looking at the file system.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
 }
 
 /// does the given file name match the elm-vendor file name?
 fn is_elm_vendor_config_file_name(name: &OsStr) -> bool {
-    name == "elm-vendor.json" || name == "elm-vendor.toml"
+    ConfigFormat::from_file_name(name).is_some()
 }
 
 impl CmdContext {
     //region helpers
+    /// Find the git repository the project is in, and where elm.json is inside of it.
+    ///
+    /// The repository can be anywhere above the project, like in a monorepo.
+    fn open_repo(&self) -> Result<(Repository, PathBuf)> {
+        let repo = Repository::discover(&self.root)
+            .with_context(|| format!("{} isn't in a git repository", self.root.display()))?;
+        let workdir = repo
+            .workdir()
+            .context("elm-vendor doesn't work in bare git repositories")?
+            .canonicalize()?;
+        let elm_json = self.root.join("elm.json").canonicalize()?;
+        let elm_json = elm_json
+            .strip_prefix(&workdir)
+            .context("elm.json isn't inside the git repository")?
+            .to_owned();
+
+        Ok((repo, elm_json))
+    }
+
     /// See if the elm.json file for the repo is commited.
     pub(crate) fn check_if_elm_json_is_commited(&self) -> Result<bool> {
-        // let repo = Repository::open(&self.root)?;
-        let repo = Repository::open_ext::<_, OsString, _>(
-            &self.root,
-            git2::RepositoryOpenFlags::NO_SEARCH,
-            std::iter::empty(),
-        )?;
-        let status = dbg!(repo.status_file(Path::new("elm.json"))?);
+        let (repo, elm_json) = self.open_repo()?;
+        let status = repo.status_file(&elm_json)?;
 
         Ok(status == git2::Status::CURRENT)
     }
 
+    /// The id of the git blob of elm.json, as of the last commit.
+    pub(crate) fn committed_elm_json(&self) -> Result<git2::Oid> {
+        let (repo, elm_json) = self.open_repo()?;
+        let tree = repo.head()?.peel_to_tree()?;
+
+        Ok(tree.get_path(&elm_json)?.id())
+    }
+
+    /// The contents of a git blob, e.g. an old elm.json.
+    pub(crate) fn read_blob(&self, id: git2::Oid) -> Result<Vec<u8>> {
+        let (repo, _) = self.open_repo()?;
+        let blob = repo.find_blob(id)?;
+
+        Ok(blob.content().to_owned())
+    }
+
     /// Try to find the elm-vendor file in the current directory.
     pub(crate) fn find_elm_vendor_json(&self) -> Result<Option<OsString>, anyhow::Error> {
         let mut elm_vendor_files = HashSet::new();
@@ -55,50 +167,158 @@ impl CmdContext {
             _ => anyhow::bail!("Multiple elm-vendor.{{json,toml}} found"),
         }
     }
+
+    /// Read the project's elm.json.
+    pub(crate) fn read_elm_json(&self) -> Result<NormalizedElmJson> {
+        let elm_json_file = fs::OpenOptions::new()
+            .read(true)
+            .open(self.root.join("elm.json"))?;
+        Ok(serde_json::from_reader::<_, ElmJson>(elm_json_file)?.into())
+    }
+
+    /// Explain what we're about to do and ask if that's okay,
+    /// unless we were told to just go ahead.
+    pub(crate) fn confirm(&self, explanation: &str) -> Result<bool> {
+        // a dry run only shows what it would write, so there's nothing to ask about
+        if self.yes || self.dry_run {
+            return Ok(true);
+        }
+
+        // stdout is for the report
+        eprintln!("{}", explanation);
+        Ok(Confirm::new().with_prompt("Sound good?").interact()?)
+    }
+
+    /// Overwrite (or create) a file in the project,
+    /// or for a dry run, show how it would change.
+    pub(crate) fn write_file(&self, path: &Path, contents: &str) -> Result<()> {
+        if self.dry_run {
+            let current = match fs::read_to_string(path) {
+                Ok(current) => current,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(err) => {
+                    return Err(err).with_context(|| format!("Couldn't read {}", path.display()))
+                }
+            };
+            let name = path.strip_prefix(&self.root).unwrap_or(path);
+            // stdout is for the report
+            eprint!(
+                "{}",
+                diff::unified_diff(&name.to_string_lossy(), &current, contents)
+            );
+            return Ok(());
+        }
+
+        self.write_atomically(path, contents.as_bytes())
+    }
+
+    /// Replace a file all at once, by writing to a temporary file first and moving it into place,
+    /// so an interruption can't leave a half-written file behind.
+    fn write_atomically(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let dir = path.parent().unwrap_or(&self.root);
+        let mut temp = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("Couldn't create a temporary file in {}", dir.display()))?;
+        temp.write_all(contents)?;
+        temp.as_file().sync_all()?;
+
+        // temporary files are only readable by us, but the real file shouldn't be.
+        // New files get the same permissions as elm.json.
+        let permissions_from = if path.exists() {
+            path.to_owned()
+        } else {
+            self.root.join("elm.json")
+        };
+        if let Ok(metadata) = fs::metadata(&permissions_from) {
+            fs::set_permissions(temp.path(), metadata.permissions())?;
+        }
+
+        temp.persist(path)
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+        Ok(())
+    }
     //endregion
 
     //region operations
 
     /// The init command.
-    pub fn init(&self) -> Result<(), anyhow::Error> {
-        if !self.yes {
-            println!("{}", INIT_PROMPT);
-            let ok = Confirm::new().with_prompt("Sound good?").interact()?;
-            if !ok {
-                return Ok(());
-            }
+    pub fn init(&self, format: ConfigFormat) -> Result<Report, anyhow::Error> {
+        if !self.confirm(INIT_PROMPT)? {
+            return Ok(Report::NothingDone);
         }
+        self.transaction(|| {
+            if let Some(_) = self.find_elm_vendor_json()? {
+                anyhow::bail!("An elm-vendor file already exists!");
+            }
 
-        if let Some(_) = self.find_elm_vendor_json()? {
-            anyhow::bail!("An elm-vendor file already exists!");
-        }
+            let elm_json = self.read_elm_json()?;
 
-        let elm_json_file = fs::OpenOptions::new()
-            .read(true)
-            .open(self.root.join("elm.json"))?;
-        let elm_json: NormalizedElmJson =
-            serde_json::from_reader::<_, ElmJson>(elm_json_file)?.into();
-
-        let elm_vendor = ElmVendor {
-            main_deps: elm_json.dependencies,
-            source_dirs: elm_json.source_dirs,
-            vendored: Vec::new(),
-            kind: elm_json.kind,
-            extras: elm_json.other_fields,
-        };
+            let elm_vendor = ElmVendor {
+                main_deps: elm_json.dependencies,
+                source_dirs: elm_json.source_dirs,
+                vendored: Vec::new(),
+                overrides: BTreeMap::new(),
+                pre_vendor_elm_json: None,
+                kind: elm_json.kind,
+                extras: elm_json.other_fields,
+            };
 
-        let elm_vendor_file = fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(self.root.join("elm-vendor.json"))?;
+            self.write_file(
+                &self.root.join(format.file_name()),
+                &format.serialize(&elm_vendor)?,
+            )?;
 
-        serde_json::to_writer_pretty(elm_vendor_file, &elm_vendor)?;
+            // TODO explain where to go from here
+            Ok(Report::Initialized {
+                file: format.file_name().to_owned(),
+            })
+        })
+    }
 
-        // TODO explain where to go from here
-        Ok(())
+    /// The install command.
+    ///
+    /// Installs with elm or lamdera, then records the new direct dependency in elm-vendor.json,
+    /// and the versions elm picked in elm-vendor.lock.
+    pub fn install(&self, dependency: &str) -> Result<Report> {
+        self.transaction(|| {
+            let mut elm_vendor = self.read_elm_vendor()?;
+            let is_lamdera = is_lamdera_project(&elm_vendor.main_deps);
+
+            let installed = elm_cli::elm_install(is_lamdera, &self.root, dependency, |plan| {
+                // elm changes elm.json itself, so all a dry run can do is show its plan
+                if self.dry_run {
+                    eprint!("{}", plan);
+                    return Ok(false);
+                }
+                self.confirm(plan)
+            })?;
+            if !installed {
+                return Ok(Report::NothingDone);
+            }
+
+            let elm_json = self.read_elm_json()?;
+            let version = elm_json.dependencies.get(dependency).with_context(|| {
+                format!(
+                    "{} isn't a direct dependency in elm.json, even after installing it",
+                    dependency
+                )
+            })?;
+
+            elm_vendor
+                .main_deps
+                .insert(dependency.to_owned(), version.clone());
+            self.write_elm_vendor(&elm_vendor)?;
+            // elm may have added or upgraded indirect dependencies too,
+            // but only `vendor` records what vendored code looks like
+            self.write_lockfile(&self.lockfile_with_versions_of(&elm_json, &elm_vendor.vendored)?)?;
+
+            Ok(Report::Installed {
+                dependency: dependency.to_owned(),
+                version: version.clone(),
+            })
+        })
     }
     //endregion
 }
 
-const INIT_PROMPT: &'static str =
-    "I'm going to extract all the user-set fields from elm.json, and add them to elm-vendor.json.";
+const INIT_PROMPT: &str =
+    "I'm going to extract all the user-set fields from elm.json, and add them to an elm-vendor config file.";
@@ -0,0 +1,103 @@
+///! Keeping two elm-vendors from changing a project at the same time,
+///! like an editor plugin and someone in a terminal.
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Held for as long as we might be changing files in the project.
+pub(crate) struct ProjectLock(fs::File);
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        // closing the file would release it anyway, but there's no harm in being explicit
+        let _ = self.0.unlock();
+    }
+}
+
+impl super::CmdContext {
+    /// Make sure nobody else is changing this project until the lock is dropped.
+    ///
+    /// Dry runs don't change anything, so they don't need (or create) a lock.
+    pub(crate) fn lock(&self) -> Result<Option<ProjectLock>> {
+        if self.dry_run {
+            return Ok(None);
+        }
+
+        let path = lock_file_for(&self.root)?;
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Couldn't open {}", path.display()))?;
+        file.try_lock_exclusive().with_context(|| {
+            format!(
+                "Another elm-vendor is already changing this project (see {})",
+                path.display()
+            )
+        })?;
+
+        Ok(Some(ProjectLock(file)))
+    }
+}
+
+/// Where the lock for the project at `root` lives.
+///
+/// It's in elm-stuff, elm's own scratch directory (which projects already leave out of git),
+/// so everything that can see the project shares it, however its temp directory is set up.
+/// It's left there afterwards (deleting it would let someone else lock a new file while we hold the old one).
+fn lock_file_for(root: &Path) -> Result<PathBuf> {
+    let elm_stuff = root.join("elm-stuff");
+    fs::create_dir_all(&elm_stuff)
+        .with_context(|| format!("Couldn't create {}", elm_stuff.display()))?;
+
+    Ok(elm_stuff.join("elm-vendor.lck"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CmdContext;
+
+    #[test]
+    fn test_only_one_lock_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = CmdContext {
+            yes: true,
+            root: dir.path().to_owned(),
+            dry_run: false,
+            elm_home: None,
+        };
+
+        let lock = ctx.lock().unwrap();
+        assert!(lock.is_some());
+        assert!(ctx.lock().is_err());
+
+        drop(lock);
+        assert!(ctx.lock().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_lock_is_kept_in_elm_stuff() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = CmdContext {
+            yes: true,
+            root: dir.path().to_owned(),
+            dry_run: false,
+            elm_home: None,
+        };
+        // the same project, by another path
+        let same_project = CmdContext {
+            yes: true,
+            root: dir.path().join("."),
+            dry_run: false,
+            elm_home: None,
+        };
+
+        drop(ctx.lock().unwrap());
+        assert!(dir.path().join("elm-stuff/elm-vendor.lck").is_file());
+
+        let _lock = ctx.lock().unwrap();
+        assert!(same_project.lock().is_err());
+    }
+}
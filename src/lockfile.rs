@@ -0,0 +1,180 @@
+///! elm-vendor.lock, which records exactly what `vendor` picked,
+///! and what every vendored package looked like at the time.
+///!
+///! That way the same elm.json comes out on every machine,
+///! and `check` notices when someone edits a vendored package without vendoring again.
+use crate::shared::*;
+use crate::vendoring::to_vendored_path;
+use crate::version::SemVer;
+use crate::writer::to_elm_style_string;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Where the lock file lives, next to elm-vendor.json.
+pub(crate) const LOCKFILE_NAME: &str = "elm-vendor.lock";
+
+/// The contents of elm-vendor.lock.
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Eq)]
+pub(crate) struct Lockfile {
+    /// The version picked for every dependency, direct or indirect.
+    /// Packages depend on ranges, so theirs is always empty.
+    pub(crate) dependencies: BTreeMap<String, SemVer>,
+    /// A hash of each vendored package's elm.json and elm code, by its path.
+    pub(crate) vendored: BTreeMap<String, String>,
+}
+
+/// Vendored packages changed since elm-vendor.lock was written.
+#[derive(Error, Debug)]
+pub(crate) struct VendoredDriftError(pub(crate) Vec<String>);
+
+impl Display for VendoredDriftError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "These vendored packages changed since elm-vendor.lock was written:"
+        )?;
+        for path in self.0.iter() {
+            writeln!(f, "\t{}", path)?;
+        }
+        writeln!(
+            f,
+            "Run elm-vendor vendor if the changes are on purpose, so they're recorded."
+        )
+    }
+}
+
+impl super::CmdContext {
+    /// Read elm-vendor.lock, if there is one.
+    pub(crate) fn read_lockfile(&self) -> Result<Option<Lockfile>> {
+        let path = self.root.join(LOCKFILE_NAME);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Couldn't read {}", path.display()))
+            }
+        };
+
+        let lockfile = serde_json::from_str(&contents)
+            .with_context(|| format!("Couldn't understand {}", path.display()))?;
+        Ok(Some(lockfile))
+    }
+
+    /// Overwrite (or create) elm-vendor.lock.
+    pub(crate) fn write_lockfile(&self, lockfile: &Lockfile) -> Result<()> {
+        self.write_file(
+            &self.root.join(LOCKFILE_NAME),
+            &to_elm_style_string(lockfile)?,
+        )
+    }
+
+    /// What elm-vendor.lock should say for the given elm.json and vendored packages.
+    pub(crate) fn lockfile_for(
+        &self,
+        elm_json: &NormalizedElmJson,
+        vendored: &[VendoredPackage],
+    ) -> Result<Lockfile> {
+        Ok(Lockfile {
+            dependencies: elm_json.pinned_versions(),
+            vendored: self.hash_vendored(vendored)?,
+        })
+    }
+
+    /// elm-vendor.lock with the versions in the given elm.json,
+    /// but still the vendored packages it recorded last time.
+    ///
+    /// That's for when only the versions changed, like after `elm install`,
+    /// so edits to vendored code don't get recorded without another `vendor`.
+    pub(crate) fn lockfile_with_versions_of(
+        &self,
+        elm_json: &NormalizedElmJson,
+        vendored: &[VendoredPackage],
+    ) -> Result<Lockfile> {
+        Ok(match self.read_lockfile()? {
+            Some(lockfile) => Lockfile {
+                dependencies: elm_json.pinned_versions(),
+                ..lockfile
+            },
+            None => self.lockfile_for(elm_json, vendored)?,
+        })
+    }
+
+    /// Every vendored package whose contents don't match what the lock file recorded,
+    /// including ones it doesn't know about, and ones that aren't vendored anymore.
+    pub(crate) fn changed_vendored(
+        &self,
+        lockfile: &Lockfile,
+        vendored: &[VendoredPackage],
+    ) -> Result<Vec<String>> {
+        let current = self.hash_vendored(vendored)?;
+
+        let mut changed: Vec<String> = current
+            .iter()
+            .filter(|(path, hash)| lockfile.vendored.get(*path) != Some(hash))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            lockfile
+                .vendored
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        Ok(changed)
+    }
+
+    fn hash_vendored(&self, vendored: &[VendoredPackage]) -> Result<BTreeMap<String, String>> {
+        self.expand_vendored(vendored)?
+            .iter()
+            .map(|package| Ok((package.path.clone(), self.hash_package(package)?)))
+            .collect()
+    }
+
+    /// Hash a vendored package's elm.json and every .elm file in its source directories,
+    /// along with where they are, so renames count as changes too.
+    fn hash_package(&self, package: &VendoredPackage) -> Result<String> {
+        let dir = self.root.join(&package.path);
+        let package_json = self.load_package_for(package)?;
+
+        let mut files = vec![dir.join("elm.json")];
+        for source_dir in package_json.source_dirs.iter() {
+            let source_dir = dir.join(source_dir);
+            let source_dir = source_dir
+                .to_str()
+                .with_context(|| format!("{} isn't valid unicode", source_dir.display()))?;
+            let pattern = format!("{}/**/*.elm", glob::Pattern::escape(source_dir));
+            for file in glob::glob(&pattern)? {
+                files.push(file?);
+            }
+        }
+        let mut files: Vec<(String, PathBuf)> = files
+            .into_iter()
+            .map(|file| {
+                let relative = file.strip_prefix(&dir).unwrap_or(&file);
+                Ok((to_vendored_path(relative)?, file))
+            })
+            .collect::<Result<_>>()?;
+        files.sort();
+        files.dedup();
+
+        let mut hasher = Sha256::new();
+        for (relative, file) in files {
+            let contents =
+                fs::read(&file).with_context(|| format!("Couldn't read {}", file.display()))?;
+            hasher.update(relative.as_bytes());
+            hasher.update([0]);
+            hasher.update((contents.len() as u64).to_le_bytes());
+            hasher.update(&contents);
+        }
+
+        Ok(format!("sha256-{:x}", hasher.finalize()))
+    }
+}
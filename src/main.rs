@@ -1,8 +1,11 @@
 // mod shared;
 // mod utils;
 
-use elm_vendor::CmdContext;
-use std::path::PathBuf;
+use anyhow::Result;
+use elm_vendor::{
+    find_project_root, path_in_project, CmdContext, ConfigFormat, OutputFormat, Report, Strategy,
+};
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -10,16 +13,34 @@ enum Subprogram {
     /// check if elm.json is committed into git
     /// ask if it's okay
     /// move stuff into elm.json
-    Vendor,
+    Vendor {
+        /// add this package directory to the vendored list first
+        dir: Option<String>,
+        /// which versions to pick for applications when more than one would work.
+        /// locked keeps what's in elm-vendor.lock (or elm.json) wherever it still works,
+        /// newest updates everything, and minimal checks that vendored packages' lower bounds are honest
+        #[structopt(long, default_value = "locked", possible_values = &["newest", "minimal", "locked"])]
+        strategy: Strategy,
+    },
     /// try to set elm.json back to its original status as much as possible
-    Unvendor,
+    Unvendor {
+        /// only unvendor this package, removing it from elm-vendor.json
+        dir: Option<String>,
+    },
     /// tests package to see if we should use elm or lamdera,
     /// tries to call one of those to use it,
     /// and then copies that new value into elm-vendor.json
-    Install,
+    Install {
+        /// the package to install, e.g. elm/json
+        dependency: String,
+    },
     /// extracts direct info to elm-vendor.json
     /// (TODO: we need fields other than the dependencies!)
-    Init,
+    Init {
+        /// write elm-vendor.json or elm-vendor.toml
+        #[structopt(long, default_value = "json", possible_values = &["json", "toml"])]
+        format: ConfigFormat,
+    },
     /// make sure the non-dependency contents of elm.json and elm-vendor.json haven't drifted.
     /// Should be run during CI!
     Check,
@@ -30,6 +51,20 @@ struct Program {
     #[structopt(short, long)]
     yes: bool,
 
+    /// run as if started in this directory.
+    /// The project is the closest directory at or above it with an elm-vendor config file,
+    /// or before init, with an elm.json
+    #[structopt(short = "C", long, default_value = ".")]
+    root: PathBuf,
+
+    /// show how elm.json and elm-vendor.json would change, without changing them
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// how to show what happened. json is meant for CI and other tools
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: OutputFormat,
+
     #[structopt(subcommand)]
     cmd: Subprogram,
 }
@@ -37,39 +72,32 @@ struct Program {
 fn main() {
     let args = Program::from_args();
 
-    let ctx = CmdContext {
-        yes: args.yes,
-        root: PathBuf::from("."),
-    };
+    let outcome = run(args.yes, args.dry_run, &args.root, args.cmd);
+    args.format.print(&outcome);
 
-    match args.cmd {
-        Subprogram::Vendor => ctx.vendor().unwrap(),
-        _ => {
-            println!("not yet")
-        }
+    if outcome.is_err() {
+        std::process::exit(1);
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     const SAMPLE_APPLICATION_JSON: &'static str = include_str!("../test-data/sample-elm-app.json");
-//     // const SAMPLE_PACKAGE_JSON: &'static str = include_str!("../test-data/sample-elm-package.json");
+fn run(yes: bool, dry_run: bool, root: &Path, cmd: Subprogram) -> Result<Report> {
+    let ctx = CmdContext {
+        yes,
+        root: find_project_root(root)?,
+        dry_run,
+        elm_home: None,
+    };
+    // directories are given from where we were run, but elm-vendor.json has them from the project
+    let in_project = |dir: Option<String>| {
+        dir.map(|dir| path_in_project(&ctx.root, root, &dir))
+            .transpose()
+    };
 
-//     // #[test]
-//     // fn test_reading_json() {
-//     //     let val: ElmJson = serde_json::from_str(SAMPLE_APPLICATION_JSON).unwrap();
-//     //     match val {
-//     //         ElmJson::Application(_) => assert!(true),
-//     //         _ => assert!(false),
-//     //     }
-//     // }
-//     // #[test]
-//     // fn test_reading_json() {
-//     //     let val: ElmJson = serde_json::from_str(SAMPLE_APPLICATION_JSON).unwrap();
-//     //     match val.dependencies {
-//     //         ElmJsonDeps::Application { .. } => assert!(true),
-//     //         ElmJsonDeps::Package(_) => assert!(false),
-//     //     }
-//     // }
-// }
+    match cmd {
+        Subprogram::Vendor { dir, strategy } => ctx.vendor(in_project(dir)?.as_deref(), strategy),
+        Subprogram::Unvendor { dir } => ctx.unvendor(in_project(dir)?.as_deref()),
+        Subprogram::Install { dependency } => ctx.install(&dependency),
+        Subprogram::Init { format } => ctx.init(format),
+        Subprogram::Check => ctx.check(),
+    }
+}
@@ -0,0 +1,294 @@
+///! What a command did (or why it couldn't),
+///! written for people or for machines like CI dashboards.
+use crate::check::{DriftError, ElmJsonDiff};
+use crate::lockfile::VendoredDriftError;
+use crate::resolver::Explanation;
+use crate::utils::MultiError;
+use crate::vendoring::{AppliedOverride, UnusedOverride, VendorChangeError};
+use crate::version::*;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// How to show the outcome of a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    /// Show the outcome of a command.
+    pub fn render(self, outcome: &Result<Report>) -> String {
+        match (self, outcome) {
+            (OutputFormat::Text, Ok(report)) => report.to_string(),
+            (OutputFormat::Text, Err(err)) => format!("Error: {:?}\n", err),
+            (OutputFormat::Json, Ok(report)) => {
+                format!("{}\n", json!({ "ok": true, "report": report }))
+            }
+            (OutputFormat::Json, Err(err)) => {
+                format!(
+                    "{}\n",
+                    json!({ "ok": false, "error": ErrorReport::from(err) })
+                )
+            }
+        }
+    }
+
+    /// Print the outcome of a command.
+    ///
+    /// Text errors go to stderr, but JSON always goes to stdout,
+    /// so there's exactly one thing to parse.
+    pub fn print(self, outcome: &Result<Report>) {
+        let rendered = self.render(outcome);
+        match (self, outcome) {
+            (OutputFormat::Text, Err(_)) => eprint!("{}", rendered),
+            _ => print!("{}", rendered),
+        }
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<OutputFormat, String> {
+        match str {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("{} isn't text or json", str)),
+        }
+    }
+}
+
+/// What a command did.
+#[derive(Serialize, Debug)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum Report {
+    /// We were told no when we asked, or there was nothing to do.
+    NothingDone,
+    /// The elm-vendor config file was created.
+    Initialized { file: String },
+    /// elm.json was rewritten, with these changes.
+    Rebuilt {
+        changes: ElmJsonDiff,
+        /// The overrides from elm-vendor.json that were used.
+        overrides: Vec<AppliedOverride>,
+        /// The overrides from elm-vendor.json that are only for packages that aren't vendored.
+        #[serde(rename = "unused-overrides")]
+        unused_overrides: Vec<UnusedOverride>,
+        /// Nothing was actually written, we only showed what would be.
+        #[serde(rename = "dry-run")]
+        dry_run: bool,
+    },
+    /// The dependency was installed and added to the main dependencies.
+    Installed {
+        dependency: String,
+        version: DependencyVersion,
+    },
+    /// elm.json is exactly what elm-vendor.json says it should be.
+    InSync,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Report::NothingDone => writeln!(f, "Nothing was changed."),
+            Report::Initialized { file } => writeln!(f, "Created {}.", file),
+            Report::Rebuilt {
+                changes,
+                overrides,
+                unused_overrides,
+                dry_run,
+            } => {
+                if changes.is_empty() {
+                    writeln!(f, "elm.json was already up to date.")?;
+                } else {
+                    if *dry_run {
+                        writeln!(f, "Would update elm.json:")?;
+                    } else {
+                        writeln!(f, "Updated elm.json:")?;
+                    }
+                    write!(f, "{}", changes)?;
+                }
+
+                if !overrides.is_empty() {
+                    writeln!(f, "Used these overrides from elm-vendor.json:")?;
+                    for applied in overrides {
+                        writeln!(f, "\t{}", applied)?;
+                    }
+                }
+                if !unused_overrides.is_empty() {
+                    writeln!(f, "Didn't use these overrides from elm-vendor.json:")?;
+                    for unused in unused_overrides {
+                        writeln!(f, "\t{}", unused)?;
+                    }
+                }
+                Ok(())
+            }
+            Report::Installed {
+                dependency,
+                version,
+            } => writeln!(
+                f,
+                "Installed {} {} and added it to the main dependencies.",
+                dependency, version
+            ),
+            Report::InSync => writeln!(f, "elm.json is in sync with elm-vendor.json."),
+        }
+    }
+}
+
+/// An error, with whatever structure we know about it.
+#[derive(Serialize, Debug)]
+struct ErrorReport {
+    message: String,
+    #[serde(flatten)]
+    details: ErrorDetails,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum ErrorDetails {
+    ConflictingDependency {
+        dependency: String,
+        versions: BTreeMap<String, DependencyVersion>,
+    },
+    NoElmJsonFound {
+        dependency: String,
+    },
+    MisnamedPackage {
+        dependency: String,
+        expected: String,
+        found: Option<String>,
+    },
+    Io {
+        dependency: String,
+    },
+    Serde {
+        dependency: String,
+    },
+    UnpinnedDependency {
+        dependency: String,
+        range: VersionRange,
+    },
+    Unresolvable {
+        explanation: Explanation,
+    },
+    ResolverFailed,
+    Drift {
+        drift: ElmJsonDiff,
+    },
+    VendoredDrift {
+        packages: Vec<String>,
+    },
+    Multiple {
+        errors: Vec<ErrorReport>,
+    },
+    Other,
+}
+
+impl From<&anyhow::Error> for ErrorReport {
+    fn from(err: &anyhow::Error) -> ErrorReport {
+        let details = if let Some(drift) = err.downcast_ref::<DriftError>() {
+            ErrorDetails::Drift {
+                drift: drift.0.clone(),
+            }
+        } else if let Some(vendored) = err.downcast_ref::<VendoredDriftError>() {
+            ErrorDetails::VendoredDrift {
+                packages: vendored.0.clone(),
+            }
+        } else if let Some(multi) = err.downcast_ref::<MultiError>() {
+            ErrorDetails::Multiple {
+                errors: multi.errors.iter().map(ErrorReport::from).collect(),
+            }
+        } else if let Some(vendor_err) = err.downcast_ref::<VendorChangeError>() {
+            vendor_err.into()
+        } else {
+            ErrorDetails::Other
+        };
+
+        ErrorReport {
+            message: format!("{:#}", err),
+            details,
+        }
+    }
+}
+
+impl From<&VendorChangeError> for ErrorDetails {
+    fn from(err: &VendorChangeError) -> ErrorDetails {
+        use VendorChangeError::*;
+        match err {
+            ConflictingDependency {
+                dependency,
+                versions,
+            } => ErrorDetails::ConflictingDependency {
+                dependency: dependency.clone(),
+                versions: versions
+                    .iter()
+                    .map(|(source, version)| (source.clone(), version.clone()))
+                    .collect(),
+            },
+            NoElmJsonFound(dependency) => ErrorDetails::NoElmJsonFound {
+                dependency: dependency.clone(),
+            },
+            MisnamedPackage {
+                path,
+                expected,
+                found,
+            } => ErrorDetails::MisnamedPackage {
+                dependency: path.clone(),
+                expected: expected.clone(),
+                found: found.clone(),
+            },
+            IoError(dependency, _) => ErrorDetails::Io {
+                dependency: dependency.clone(),
+            },
+            SerdeError(dependency, _) => ErrorDetails::Serde {
+                dependency: dependency.clone(),
+            },
+            UnpinnedDependency(dependency, range) => ErrorDetails::UnpinnedDependency {
+                dependency: dependency.clone(),
+                range: range.clone(),
+            },
+            Unresolvable(explanation) => ErrorDetails::Unresolvable {
+                explanation: explanation.clone(),
+            },
+            ResolverFailed(_) => ErrorDetails::ResolverFailed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_keep_their_structure() {
+        let errors = vec![
+            anyhow::Error::from(VendorChangeError::NoElmJsonFound("vendor/a".to_owned())),
+            anyhow::anyhow!("something else"),
+        ];
+        let outcome = Err(MultiError::from(errors).into());
+
+        let rendered: serde_json::Value =
+            serde_json::from_str(&OutputFormat::Json.render(&outcome)).unwrap();
+        assert_eq!(rendered["ok"], false);
+        assert_eq!(rendered["error"]["kind"], "multiple");
+        assert_eq!(rendered["error"]["errors"][0]["kind"], "no-elm-json-found");
+        assert_eq!(rendered["error"]["errors"][0]["dependency"], "vendor/a");
+        assert_eq!(rendered["error"]["errors"][1]["kind"], "other");
+        assert_eq!(rendered["error"]["errors"][1]["message"], "something else");
+    }
+}
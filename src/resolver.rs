@@ -0,0 +1,628 @@
+///! Picking one concrete version of every dependency,
+///! such that the main package and every vendored package are happy.
+///!
+///! The heavy lifting is done by pubgrub.
+///! The main package and each vendored package are modeled as packages of their own,
+///! which all depend on the actual elm packages.
+use crate::elm_home;
+use crate::vendoring::{AppliedOverride, VendorChangeError};
+use crate::version::*;
+use pubgrub::error::PubGrubError;
+use pubgrub::range::Range;
+use pubgrub::report::{DerivationTree, External};
+use pubgrub::solver::{
+    choose_package_with_fewest_versions, resolve, Dependencies, DependencyProvider,
+};
+use pubgrub::type_aliases::Map;
+use pubgrub::version::Version;
+use ranges::{Domain, GenericRange};
+use serde::Serialize;
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::str::FromStr;
+use tap::Conv;
+
+/// Requested dependencies, by the package that requested them.
+pub(crate) type Requirements = BTreeMap<String, HashMap<String, DependencyVersion>>;
+
+/// Every version that exists of each package.
+pub(crate) type AvailableVersions = HashMap<String, Vec<SemVer>>;
+
+impl Version for SemVer {
+    fn lowest() -> SemVer {
+        SemVer {
+            major: 0,
+            minor: 0,
+            patch: 0,
+        }
+    }
+
+    fn bump(&self) -> SemVer {
+        self.successor()
+            .expect("no version comes after the highest version")
+    }
+}
+
+/// Which versions to prefer when more than one would work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// The newest versions, like elm picks.
+    Newest,
+    /// The oldest versions, to make sure the lower bounds packages ask for are honest.
+    Minimal,
+    /// The versions in elm-vendor.lock (or elm.json, if there isn't one yet),
+    /// wherever they still work, and otherwise the newest.
+    Locked,
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Strategy::Newest => write!(f, "newest"),
+            Strategy::Minimal => write!(f, "minimal"),
+            Strategy::Locked => write!(f, "locked"),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Strategy, String> {
+        match str {
+            "newest" => Ok(Strategy::Newest),
+            "minimal" => Ok(Strategy::Minimal),
+            "locked" => Ok(Strategy::Locked),
+            _ => Err(format!("{} isn't newest, minimal, or locked", str)),
+        }
+    }
+}
+
+/// Anything the solver can pick a version for.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Package {
+    /// Stands in for the whole project, depending on every source of requirements.
+    Root,
+    /// The main package or a vendored package.
+    /// These only have one (meaningless) version.
+    Source(String),
+    /// An actual elm package.
+    Elm(String),
+}
+
+impl Display for Package {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Package::Root => write!(f, "this project"),
+            Package::Source(source) => write!(f, "{}", source),
+            Package::Elm(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Convert elm's version constraints into pubgrub's.
+fn to_pubgrub_range(version: &DependencyVersion) -> Range<SemVer> {
+    let range = version.conv::<GenericRange<SemVer>>();
+
+    let lower = match range.start_bound() {
+        Bound::Included(lower) => Range::higher_than(*lower),
+        Bound::Excluded(lower) => Range::higher_than(lower.bump()),
+        Bound::Unbounded => Range::any(),
+    };
+    let higher = match range.end_bound() {
+        Bound::Included(higher) => Range::strictly_lower_than(higher.bump()),
+        Bound::Excluded(higher) => Range::strictly_lower_than(*higher),
+        Bound::Unbounded => Range::any(),
+    };
+
+    lower.intersection(&higher)
+}
+
+struct ElmDependencyProvider<'a> {
+    requirements: &'a Requirements,
+    available: &'a AvailableVersions,
+    /// Versions to use no matter what an elm package asks for.
+    overrides: &'a BTreeMap<String, DependencyVersion>,
+    strategy: Strategy,
+    /// The versions from last time, for `Strategy::Locked`.
+    locked: &'a BTreeMap<String, SemVer>,
+    /// Where to find what each version of an elm package depends on.
+    elm_home: Option<&'a Path>,
+    /// Overrides we used, and for which version of which package.
+    applied: RefCell<Vec<(String, SemVer, AppliedOverride)>>,
+    /// Package versions we considered, but couldn't find in elm's cache.
+    uncached: RefCell<Vec<String>>,
+}
+
+impl ElmDependencyProvider<'_> {
+    /// All versions of the package, most preferred first.
+    fn list_versions(&self, package: &Package) -> std::vec::IntoIter<SemVer> {
+        match package {
+            Package::Root | Package::Source(_) => vec![SemVer::lowest()].into_iter(),
+            Package::Elm(name) => {
+                let mut versions = self.available.get(name).cloned().unwrap_or_default();
+                match self.strategy {
+                    Strategy::Minimal => versions.sort_unstable(),
+                    Strategy::Newest => versions.sort_unstable_by(|a, b| b.cmp(a)),
+                    Strategy::Locked => {
+                        versions.sort_unstable_by(|a, b| b.cmp(a));
+                        let locked = self.locked.get(name);
+                        // stable, so everything else stays newest first
+                        versions.sort_by_key(|version| Some(version) != locked);
+                    }
+                }
+                versions.into_iter()
+            }
+        }
+    }
+}
+
+impl ElmDependencyProvider<'_> {
+    /// What to use for what a version of an elm package asks for,
+    /// keeping track of any override we use instead.
+    fn override_for<'v>(
+        &'v self,
+        package: &str,
+        package_version: &SemVer,
+        dependency: &str,
+        requested: &'v DependencyVersion,
+    ) -> &'v DependencyVersion {
+        let used = match self.overrides.get(dependency) {
+            Some(used) if used != requested => used,
+            _ => return requested,
+        };
+
+        self.applied.borrow_mut().push((
+            package.to_owned(),
+            *package_version,
+            AppliedOverride {
+                dependency: dependency.to_owned(),
+                version: used.clone(),
+                instead_of: vec![(
+                    format!("{} {}", package, package_version),
+                    requested.clone(),
+                )]
+                .into_iter()
+                .collect(),
+            },
+        ));
+        used
+    }
+}
+
+impl DependencyProvider<Package, SemVer> for ElmDependencyProvider<'_> {
+    fn choose_package_version<T: Borrow<Package>, U: Borrow<Range<SemVer>>>(
+        &self,
+        potential_packages: impl Iterator<Item = (T, U)>,
+    ) -> Result<(T, Option<SemVer>), Box<dyn Error>> {
+        Ok(choose_package_with_fewest_versions(
+            |package| self.list_versions(package),
+            potential_packages,
+        ))
+    }
+
+    fn get_dependencies(
+        &self,
+        package: &Package,
+        version: &SemVer,
+    ) -> Result<Dependencies<Package, SemVer>, Box<dyn Error>> {
+        let dependencies: Map<Package, Range<SemVer>> = match package {
+            Package::Root => self
+                .requirements
+                .keys()
+                .map(|source| {
+                    (
+                        Package::Source(source.clone()),
+                        Range::exact(SemVer::lowest()),
+                    )
+                })
+                .collect(),
+            Package::Source(source) => self.requirements[source]
+                .iter()
+                .map(|(dependency, version)| {
+                    (Package::Elm(dependency.clone()), to_pubgrub_range(version))
+                })
+                .collect(),
+            Package::Elm(name) => {
+                match elm_home::read_cached_dependencies(self.elm_home, name, version)? {
+                    Some(dependencies) => dependencies
+                        .iter()
+                        .map(|(dependency, requested)| {
+                            let used = self.override_for(name, version, dependency, requested);
+                            (Package::Elm(dependency.clone()), to_pubgrub_range(used))
+                        })
+                        .collect(),
+                    None => {
+                        self.uncached
+                            .borrow_mut()
+                            .push(format!("{} {}", name, version));
+                        return Ok(Dependencies::Unknown);
+                    }
+                }
+            }
+        };
+
+        Ok(Dependencies::Known(dependencies))
+    }
+}
+
+/// Without any knowledge of the package registry,
+/// the best we can do is trust that every version mentioned by a requirement exists.
+pub(crate) fn versions_mentioned_in(requirements: &Requirements) -> AvailableVersions {
+    let mut available = AvailableVersions::new();
+
+    for dependencies in requirements.values() {
+        for (dependency, version) in dependencies.iter() {
+            let mentioned = match version {
+                DependencyVersion::SpecificVersion(version) => *version,
+                DependencyVersion::VersionRange(range) => match range.lower_relation {
+                    Relation::LTE => range.lower,
+                    Relation::LT => continue,
+                },
+            };
+
+            let versions = available.entry(dependency.clone()).or_default();
+            if !versions.contains(&mentioned) {
+                versions.push(mentioned);
+            }
+        }
+    }
+
+    available
+}
+
+/// Add more known versions, e.g. from elm's cached package registry.
+pub(crate) fn extend_available(available: &mut AvailableVersions, more: AvailableVersions) {
+    for (package, versions) in more {
+        let known = available.entry(package).or_default();
+        for version in versions {
+            if !known.contains(&version) {
+                known.push(version);
+            }
+        }
+    }
+}
+
+/// Pick one version of every requested dependency that satisfies all of the requirements,
+/// along with everything those depend on.
+///
+/// The overrides replace whatever any elm package asks for,
+/// and the ones that made it into the solution are given back too.
+/// `locked` is only looked at for `Strategy::Locked`.
+pub(crate) fn resolve_versions(
+    requirements: &Requirements,
+    available: &AvailableVersions,
+    overrides: &BTreeMap<String, DependencyVersion>,
+    strategy: Strategy,
+    locked: &BTreeMap<String, SemVer>,
+    elm_home: Option<&Path>,
+) -> Result<(BTreeMap<String, SemVer>, Vec<AppliedOverride>), VendorChangeError> {
+    let provider = ElmDependencyProvider {
+        requirements,
+        available,
+        overrides,
+        strategy,
+        locked,
+        elm_home,
+        applied: RefCell::new(Vec::new()),
+        uncached: RefCell::new(Vec::new()),
+    };
+
+    match resolve(&provider, Package::Root, SemVer::lowest()) {
+        Ok(solution) => {
+            let resolved: BTreeMap<String, SemVer> = solution
+                .into_iter()
+                .filter_map(|(package, version)| match package {
+                    Package::Elm(name) => Some((name, version)),
+                    Package::Root | Package::Source(_) => None,
+                })
+                .collect();
+            // the solver looks at versions it doesn't end up picking, which don't count
+            let applied = provider
+                .applied
+                .into_inner()
+                .into_iter()
+                .filter(|(package, version, _)| resolved.get(package) == Some(version))
+                .map(|(_, _, applied)| applied)
+                .collect();
+            Ok((resolved, applied))
+        }
+        Err(PubGrubError::NoSolution(derivation_tree)) => {
+            let mut explanation = Explanation::from(&derivation_tree);
+            explanation.uncached = provider.uncached.into_inner();
+            Err(VendorChangeError::Unresolvable(explanation))
+        }
+        Err(err) => Err(VendorChangeError::ResolverFailed(err.to_string())),
+    }
+}
+
+//region explanations
+/// Why there's no set of versions that works for every package,
+/// as the chains of requirements that ran into each other.
+#[derive(Serialize, Debug, Clone, Default)]
+pub(crate) struct Explanation {
+    /// What the main package and vendored packages asked for that played a part,
+    /// and what those asked for in turn.
+    pub(crate) requirements: Vec<Requirement>,
+    /// Versions that were needed, but elm has never heard of.
+    pub(crate) unavailable: Vec<String>,
+    /// Package versions that weren't in elm's package cache, so their dependencies are unknown.
+    pub(crate) uncached: Vec<String>,
+}
+
+/// A package, the versions of it that were asked for, and what those need.
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct Requirement {
+    pub(crate) package: String,
+    /// Missing for the main package and vendored packages, which don't have versions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) version: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) needs: Vec<Requirement>,
+}
+
+/// `package` at `versions` depends on `dependency` at `dependency_versions`.
+struct Edge<'a> {
+    package: &'a Package,
+    versions: &'a Range<SemVer>,
+    dependency: &'a Package,
+    dependency_versions: &'a Range<SemVer>,
+}
+
+impl Explanation {
+    /// Every path through the requirements, written out like
+    /// "vendor/a needs foo 2.0.0 <= v < 3.0.0, which needs bar 1.0.0 <= v < 2.0.0".
+    pub(crate) fn chains(&self) -> Vec<String> {
+        self.requirements
+            .iter()
+            .flat_map(|requirement| requirement.chains(" needs "))
+            .collect()
+    }
+}
+
+impl Requirement {
+    fn chains(&self, connector: &str) -> Vec<String> {
+        let this = match &self.version {
+            Some(version) => format!("{} {}", self.package, version),
+            None => self.package.clone(),
+        };
+        if self.needs.is_empty() {
+            return vec![this];
+        }
+
+        self.needs
+            .iter()
+            .flat_map(|need| need.chains(", which needs "))
+            .map(|rest| format!("{}{}{}", this, connector, rest))
+            .collect()
+    }
+}
+
+impl From<&DerivationTree<Package, SemVer>> for Explanation {
+    fn from(tree: &DerivationTree<Package, SemVer>) -> Explanation {
+        let mut edges = Vec::new();
+        let mut unavailable = BTreeSet::new();
+        collect_incompatibilities(tree, &mut edges, &mut unavailable);
+
+        // every chain starts at whoever wrote the requirement down
+        let mut sources: Vec<&Package> = edges
+            .iter()
+            .map(|edge| edge.package)
+            .filter(|package| matches!(package, Package::Source(_)))
+            .collect();
+        sources.sort_by_key(|source| source.to_string());
+        sources.dedup();
+        let requirements = sources
+            .into_iter()
+            .map(|source| Requirement {
+                package: source.to_string(),
+                version: None,
+                needs: needs_of(source, &Range::any(), &edges, &mut vec![source]),
+            })
+            .collect();
+
+        Explanation {
+            requirements,
+            unavailable: unavailable.into_iter().collect(),
+            uncached: Vec::new(),
+        }
+    }
+}
+
+/// Gather the facts that pubgrub's reasoning rests on.
+fn collect_incompatibilities<'a>(
+    tree: &'a DerivationTree<Package, SemVer>,
+    edges: &mut Vec<Edge<'a>>,
+    unavailable: &mut BTreeSet<String>,
+) {
+    match tree {
+        DerivationTree::External(External::FromDependencyOf(
+            package,
+            versions,
+            dependency,
+            dependency_versions,
+        )) => {
+            let seen = edges.iter().any(|edge| {
+                edge.package == package
+                    && edge.versions == versions
+                    && edge.dependency == dependency
+                    && edge.dependency_versions == dependency_versions
+            });
+            if !seen {
+                edges.push(Edge {
+                    package,
+                    versions,
+                    dependency,
+                    dependency_versions,
+                });
+            }
+        }
+        DerivationTree::External(External::NoVersions(package, versions)) => {
+            unavailable.insert(format!("{} {}", package, versions));
+        }
+        // the uncached list already covers these, and the root is always there
+        DerivationTree::External(External::UnavailableDependencies(..))
+        | DerivationTree::External(External::NotRoot(..)) => {}
+        DerivationTree::Derived(derived) => {
+            collect_incompatibilities(&derived.cause1, edges, unavailable);
+            collect_incompatibilities(&derived.cause2, edges, unavailable);
+        }
+    }
+}
+
+/// What the given versions of a package need, as far as the conflict is concerned.
+fn needs_of<'a>(
+    package: &Package,
+    versions: &Range<SemVer>,
+    edges: &[Edge<'a>],
+    path: &mut Vec<&'a Package>,
+) -> Vec<Requirement> {
+    let mut needs = Vec::new();
+    for edge in edges {
+        let relevant = edge.package == package
+            && edge.versions.intersection(versions) != Range::none()
+            // elm doesn't allow cycles, but a broken cache shouldn't send us spinning
+            && !path.contains(&edge.dependency);
+        if !relevant {
+            continue;
+        }
+
+        path.push(edge.dependency);
+        needs.push(Requirement {
+            package: edge.dependency.to_string(),
+            version: Some(edge.dependency_versions.to_string()),
+            needs: needs_of(edge.dependency, edge.dependency_versions, edges, path),
+        });
+        path.pop();
+    }
+    needs
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for chain in self.chains() {
+            writeln!(f, "\t{}", chain)?;
+        }
+        if !self.unavailable.is_empty() {
+            writeln!(
+                f,
+                "These versions aren't in elm's package registry: {}",
+                self.unavailable.join(", ")
+            )?;
+        }
+        if !self.uncached.is_empty() {
+            writeln!(
+                f,
+                "These weren't in elm's package cache, so their dependencies are unknown: {}",
+                self.uncached.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+//endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Only read packages from our fixture, never the real ~/.elm.
+    fn fixture_elm_home() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/elm-home")
+    }
+
+    #[test]
+    fn test_transitive_conflicts_are_explained_as_chains() {
+        let requirement = |version: &str| -> DependencyVersion { version.parse().unwrap() };
+
+        let mut requirements = Requirements::new();
+        requirements.insert(
+            "main package".to_owned(),
+            vec![("elm/http".to_owned(), requirement("2.0.0"))]
+                .into_iter()
+                .collect(),
+        );
+        requirements.insert(
+            "vendor/a".to_owned(),
+            vec![("elm/bytes".to_owned(), requirement("2.0.0 <= v < 3.0.0"))]
+                .into_iter()
+                .collect(),
+        );
+        // both versions of elm/bytes are in the fixture's cache,
+        // so the only conflict is the one through elm/http, whichever package is picked first
+        let mut available = versions_mentioned_in(&requirements);
+        available.insert(
+            "elm/bytes".to_owned(),
+            vec!["1.0.8".parse().unwrap(), "2.0.0".parse().unwrap()],
+        );
+
+        let explanation = match resolve_versions(
+            &requirements,
+            &available,
+            &BTreeMap::new(),
+            Strategy::Newest,
+            &BTreeMap::new(),
+            Some(&fixture_elm_home()),
+        ) {
+            Err(VendorChangeError::Unresolvable(explanation)) => explanation,
+            other => panic!("expected a conflict, got {:?}", other),
+        };
+
+        let chains = explanation.chains();
+        assert!(chains.contains(
+            &"main package needs elm/http 2.0.0, which needs elm/bytes 1.0.0 <= v < 2.0.0"
+                .to_owned()
+        ));
+        assert!(chains.contains(&"vendor/a needs elm/bytes 2.0.0 <= v < 3.0.0".to_owned()));
+    }
+
+    #[test]
+    fn test_strategies_prefer_different_versions() {
+        let mut requirements = Requirements::new();
+        requirements.insert(
+            "main package".to_owned(),
+            vec![("elm/core".to_owned(), "1.0.0 <= v < 2.0.0".parse().unwrap())]
+                .into_iter()
+                .collect(),
+        );
+        let mut available = AvailableVersions::new();
+        available.insert(
+            "elm/core".to_owned(),
+            vec!["1.0.2".parse().unwrap(), "1.0.5".parse().unwrap()],
+        );
+        let resolve_with = |strategy, locked: &[(&str, &str)]| -> String {
+            let locked = locked
+                .iter()
+                .map(|(name, version)| (name.to_string(), version.parse().unwrap()))
+                .collect();
+            let (resolved, _) = resolve_versions(
+                &requirements,
+                &available,
+                &BTreeMap::new(),
+                strategy,
+                &locked,
+                Some(&fixture_elm_home()),
+            )
+            .unwrap();
+            resolved["elm/core"].to_string()
+        };
+
+        assert_eq!(resolve_with(Strategy::Newest, &[]), "1.0.5");
+        assert_eq!(resolve_with(Strategy::Minimal, &[]), "1.0.2");
+        assert_eq!(
+            resolve_with(Strategy::Locked, &[("elm/core", "1.0.2")]),
+            "1.0.2"
+        );
+        // elm.json has a version that can't be used anymore, so fall back to the newest
+        assert_eq!(
+            resolve_with(Strategy::Locked, &[("elm/core", "2.0.0")]),
+            "1.0.5"
+        );
+    }
+}
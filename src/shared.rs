@@ -3,45 +3,289 @@
 /// and elm-vendor.json
 
 use super::version::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt::{self, Formatter};
+use std::path::{Path, PathBuf};
 
 /// The elm-vendor.{json,toml} config file
 #[derive(Deserialize, Serialize)]
 pub(crate) struct ElmVendor {
     #[serde(rename = "main-dependencies")]
-    pub(crate) main_deps: HashMap<String, DependencyVersion>,
+    #[serde(serialize_with = "serialize_in_elm_order")]
+    pub(crate) main_deps: BTreeMap<String, DependencyVersion>,
     #[serde(rename = "source-directories")]
     pub(crate) source_dirs: Vec<PathBuf>,
-    pub(crate) vendored: Vec<String>,
+    pub(crate) vendored: Vec<VendoredPackage>,
+    /// Versions to use instead of what the vendored packages ask for, by dependency name.
+    /// Only for applications.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) overrides: BTreeMap<String, VersionOverride>,
+    /// The git blob id of elm.json from before anything was vendored,
+    /// so `unvendor` can put it back exactly.
+    #[serde(rename = "pre-vendor-elm-json")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pre_vendor_elm_json: Option<String>,
     #[serde(rename = "type")]
     pub(crate) kind: ElmJsonKind,
-    pub(crate) extras: HashMap<String, serde_json::Value>,
+    pub(crate) extras: BTreeMap<String, serde_json::Value>,
 }
 
-/// The elm.json file
+/// A vendored package listed in elm-vendor.json.
+///
+/// Usually written as just its path, but can also be an object
+/// with details about where it came from.
+///
+/// The derived impls are only for the object form; the `Serialize` and `Deserialize` impls
+/// below pick between the two.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(remote = "Self", deny_unknown_fields)]
+pub(crate) struct VendoredPackage {
+    /// The directory the package lives in, relative to the project root.
+    pub(crate) path: String,
+    /// The name from the package's elm.json, like `author/project`.
+    /// If given, the elm.json has to agree, so patterns can't have one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    /// Where it came from: a git URL, or a registry `author/project@version`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<String>,
+    /// The commit, tag, or version of the source it was copied from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) revision: Option<String>,
+    /// Why it's vendored, what was patched, and so on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) notes: Option<String>,
+}
+
+impl VendoredPackage {
+    /// A vendored package we only know the path of.
+    pub(crate) fn at(path: impl Into<String>) -> VendoredPackage {
+        VendoredPackage {
+            path: path.into(),
+            name: None,
+            source: None,
+            revision: None,
+            notes: None,
+        }
+    }
+
+    /// Whether this is a glob like `vendor/*`, standing in for every package it matches.
+    pub(crate) fn is_pattern(&self) -> bool {
+        self.path.contains(&['*', '?', '['][..])
+    }
+
+    fn is_path_only(&self) -> bool {
+        *self == VendoredPackage::at(self.path.clone())
+    }
+}
+
+/// A version to use instead of what vendored packages' elm.json files ask for.
+///
+/// Written as just the version to use it for every vendored package
+/// (and every package they depend on),
+/// or as an object to only use it for one vendored package.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub(crate) enum VersionOverride {
+    Everywhere(DependencyVersion),
+    Only {
+        version: DependencyVersion,
+        /// The path of the vendored package, as it's listed in `vendored`.
+        package: String,
+    },
+}
+
+impl VersionOverride {
+    pub(crate) fn version(&self) -> &DependencyVersion {
+        match self {
+            VersionOverride::Everywhere(version) => version,
+            VersionOverride::Only { version, .. } => version,
+        }
+    }
+
+    /// Whether this is used for what the vendored package at the given path asks for.
+    pub(crate) fn applies_to(&self, path: &str) -> bool {
+        match self {
+            VersionOverride::Everywhere(_) => true,
+            VersionOverride::Only { package, .. } => Path::new(package) == Path::new(path),
+        }
+    }
+}
+
+impl Serialize for VendoredPackage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // plain paths stay plain, so adding details to one entry doesn't rewrite the others
+        if self.is_path_only() {
+            serializer.serialize_str(&self.path)
+        } else {
+            VendoredPackage::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VendoredPackage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<VendoredPackage, D::Error> {
+        struct VendoredPackageVisitor;
+
+        impl<'de> Visitor<'de> for VendoredPackageVisitor {
+            type Value = VendoredPackage;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                write!(f, "a directory, or an object with its path and details")
+            }
+
+            fn visit_str<E: de::Error>(self, path: &str) -> Result<VendoredPackage, E> {
+                Ok(VendoredPackage::at(path))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<VendoredPackage, A::Error> {
+                let package = VendoredPackage::deserialize(MapAccessDeserializer::new(map))?;
+                // every package a pattern matches would have to have that same name
+                if package.is_pattern() && package.name.is_some() {
+                    return Err(de::Error::custom(format!(
+                        "{} is a pattern that can match more than one package, so it can't have a name",
+                        package.path
+                    )));
+                }
+                Ok(package)
+            }
+        }
+
+        deserializer.deserialize_any(VendoredPackageVisitor)
+    }
+}
+
+/// The elm.json file.
+///
+/// Fields are declared in the same order elm writes them.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub(crate) enum ElmJson {
+    Application(ApplicationJson),
+    Package(PackageJson),
+}
+
+/// The elm.json file of an application.
 #[derive(Deserialize, Serialize)]
-pub(crate) struct ElmJson {
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ApplicationJson {
     #[serde(rename = "source-directories")]
     pub(crate) source_dirs: Vec<PathBuf>,
-
+    pub(crate) elm_version: SemVer,
+    pub(crate) dependencies: ApplicationDeps,
+    pub(crate) test_dependencies: ApplicationDeps,
+    /// Fields elm-vendor doesn't know about, written back as they were.
     #[serde(flatten)]
-    pub(crate) dependencies: ElmJsonDeps,
+    pub(crate) other: BTreeMap<String, serde_json::Value>,
+}
 
-    #[serde(flatten)]
-    pub(crate) other_fields: HashMap<String, serde_json::Value>,
+/// Applications list every package they use,
+/// split by whether they use it themselves or only through another package.
+#[derive(Deserialize, Serialize, Default)]
+pub(crate) struct ApplicationDeps {
+    #[serde(serialize_with = "serialize_in_elm_order")]
+    pub(crate) direct: BTreeMap<String, SemVer>,
+    #[serde(serialize_with = "serialize_in_elm_order")]
+    pub(crate) indirect: BTreeMap<String, SemVer>,
 }
 
-/// The dependencies section of the elm.json file.
+/// The elm.json file of a package.
 #[derive(Deserialize, Serialize)]
-#[serde(tag = "type", content = "dependencies", rename_all = "lowercase")]
-pub(crate) enum ElmJsonDeps {
-    Application { direct: HashMap<String, SemVer> },
-    Package(HashMap<String, DependencyVersion>),
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PackageJson {
+    pub(crate) name: String,
+    pub(crate) summary: String,
+    pub(crate) license: String,
+    pub(crate) version: SemVer,
+    pub(crate) exposed_modules: ExposedModules,
+    pub(crate) elm_version: VersionRange,
+    #[serde(serialize_with = "serialize_in_elm_order")]
+    pub(crate) dependencies: BTreeMap<String, DependencyVersion>,
+    #[serde(serialize_with = "serialize_in_elm_order")]
+    pub(crate) test_dependencies: BTreeMap<String, DependencyVersion>,
+    /// Fields elm-vendor doesn't know about, written back as they were.
+    #[serde(flatten)]
+    pub(crate) other: BTreeMap<String, serde_json::Value>,
+}
+
+/// elm sorts packages by author, and then by project.
+/// That isn't quite alphabetical: `elm/core` comes before `elm-community/list-extra`.
+fn serialize_in_elm_order<S: Serializer, V: Serialize>(
+    dependencies: &BTreeMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut dependencies: Vec<_> = dependencies.iter().collect();
+    dependencies.sort_by_key(|(name, _)| name.split_once('/').unwrap_or((name, "")));
+    serializer.collect_map(dependencies)
 }
 
-#[derive(Serialize, Deserialize)]
+/// A package's exposed modules,
+/// either as a plain list or grouped under headings for the docs.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ExposedModules {
+    List(Vec<String>),
+    /// Kept in order, since the order of the groups is the order of the docs.
+    Grouped(Vec<(String, Vec<String>)>),
+}
+
+impl Serialize for ExposedModules {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ExposedModules::List(modules) => {
+                let mut seq = serializer.serialize_seq(Some(modules.len()))?;
+                for module in modules {
+                    seq.serialize_element(module)?;
+                }
+                seq.end()
+            }
+            ExposedModules::Grouped(groups) => {
+                let mut map = serializer.serialize_map(Some(groups.len()))?;
+                for (heading, modules) in groups {
+                    map.serialize_entry(heading, modules)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExposedModules {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ExposedModules, D::Error> {
+        struct ExposedModulesVisitor;
+
+        impl<'de> Visitor<'de> for ExposedModulesVisitor {
+            type Value = ExposedModules;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                write!(f, "a list of modules, or an object of lists of modules")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ExposedModules, A::Error> {
+                let mut modules = Vec::new();
+                while let Some(module) = seq.next_element()? {
+                    modules.push(module);
+                }
+                Ok(ExposedModules::List(modules))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ExposedModules, A::Error> {
+                let mut groups = Vec::new();
+                while let Some(group) = map.next_entry()? {
+                    groups.push(group);
+                }
+                Ok(ExposedModules::Grouped(groups))
+            }
+        }
+
+        deserializer.deserialize_any(ExposedModulesVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 #[serde(rename_all = "lowercase")]
 pub(crate) enum ElmJsonKind {
     Application,
@@ -52,44 +296,217 @@ pub(crate) enum ElmJsonKind {
 pub(crate) struct NormalizedElmJson {
     pub(crate) kind: ElmJsonKind,
     pub(crate) source_dirs: Vec<PathBuf>,
-    pub(crate) dependencies: HashMap<String, DependencyVersion>,
-    pub(crate) other_fields: HashMap<String, serde_json::Value>,
+    pub(crate) dependencies: BTreeMap<String, DependencyVersion>,
+    /// Everything the direct dependencies depend on (applications only).
+    pub(crate) indirect_dependencies: BTreeMap<String, SemVer>,
+    /// Every field except `type`, `dependencies`, and an application's `source-directories`,
+    /// exactly as they appear in elm.json.
+    pub(crate) other_fields: BTreeMap<String, serde_json::Value>,
 }
 
+/// The fields of an application's elm.json that `NormalizedElmJson` pulls out of `other_fields`.
+const APPLICATION_NORMALIZED_FIELDS: [&str; 3] = ["type", "source-directories", "dependencies"];
+
+/// The fields of a package's elm.json that `NormalizedElmJson` pulls out of `other_fields`.
+/// Packages always use `src`, so a `source-directories` written anyway is just kept.
+const PACKAGE_NORMALIZED_FIELDS: [&str; 2] = ["type", "dependencies"];
+
+/// Packages can't choose their source directories.
+pub(crate) const PACKAGE_SOURCE_DIR: &str = "src";
+
 impl From<ElmJson> for NormalizedElmJson {
     fn from(json: ElmJson) -> NormalizedElmJson {
-        let (kind, deps): (ElmJsonKind, HashMap<String, DependencyVersion>) =
-            match json.dependencies {
-                ElmJsonDeps::Application { direct } => (
-                    ElmJsonKind::Application,
-                    direct.into_iter().map(|(k, v)| (k, v.into())).collect(),
-                ),
-                ElmJsonDeps::Package(deps) => (ElmJsonKind::Package, deps),
-            };
-        NormalizedElmJson {
-            kind: kind,
-            source_dirs: json.source_dirs,
-            dependencies: deps,
-            other_fields: json.other_fields,
+        let fields = serde_json::to_value(&json);
+        let mut other_fields: BTreeMap<String, serde_json::Value> = match fields {
+            Ok(serde_json::Value::Object(fields)) => fields.into_iter().collect(),
+            _ => unreachable!("elm.json always serializes to an object"),
+        };
+        let normalized_fields: &[&str] = match json {
+            ElmJson::Application(_) => &APPLICATION_NORMALIZED_FIELDS,
+            ElmJson::Package(_) => &PACKAGE_NORMALIZED_FIELDS,
+        };
+        for field in normalized_fields {
+            other_fields.remove(*field);
+        }
+
+        match json {
+            ElmJson::Application(application) => NormalizedElmJson {
+                kind: ElmJsonKind::Application,
+                source_dirs: application.source_dirs,
+                dependencies: application
+                    .dependencies
+                    .direct
+                    .into_iter()
+                    .map(|(k, v)| (k, v.into()))
+                    .collect(),
+                indirect_dependencies: application.dependencies.indirect,
+                other_fields,
+            },
+            ElmJson::Package(package) => NormalizedElmJson {
+                kind: ElmJsonKind::Package,
+                source_dirs: vec![PathBuf::from(PACKAGE_SOURCE_DIR)],
+                dependencies: package.dependencies,
+                indirect_dependencies: BTreeMap::new(),
+                other_fields,
+            },
         }
     }
 }
 
-// impl From<NormalizedElmJson> for ElmJson {
-//     fn from(json: NormalizedElmJson) -> ElmJson {
-//         ElmJson {
-//             source_dirs: json.source_dirs,
-//             other_fields: json.other_fields,
-//             dependencies: match json.kind {
-//                 ElmJsonKind::Application => ElmJsonDeps::Application {
-//                     direct: json.dependencies,
-//                 },
-//                 ElmJsonKind::Package => ElmJsonDeps::Package(json.dependencies),
-//             },
-//         }
-//     }
-// }
+impl NormalizedElmJson {
+    /// Every exact version, direct or indirect.
+    pub(crate) fn pinned_versions(&self) -> BTreeMap<String, SemVer> {
+        let direct = self
+            .dependencies
+            .iter()
+            .filter_map(|(dependency, version)| match version {
+                DependencyVersion::SpecificVersion(version) => Some((dependency.clone(), *version)),
+                DependencyVersion::VersionRange(_) => None,
+            });
+        let indirect = self
+            .indirect_dependencies
+            .iter()
+            .map(|(dependency, version)| (dependency.clone(), *version));
 
-pub(crate) fn is_lamdera_project<V>(deps: &HashMap<String, V>) -> bool {
+        direct.chain(indirect).collect()
+    }
+}
+
+pub(crate) fn is_lamdera_project<V>(deps: &BTreeMap<String, V>) -> bool {
     deps.contains_key("lamdera/core")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::to_elm_style_string;
+    const SAMPLE_APPLICATION_JSON: &str = include_str!("../test-data/sample-elm-app.json");
+    const SAMPLE_PACKAGE_JSON: &str =
+        include_str!("../test-data/sample-elm-package-canonical.json");
+    /// Has a `source-directories`, which elm doesn't write for packages.
+    const SAMPLE_PACKAGE_WITH_SOURCE_DIRS_JSON: &str =
+        include_str!("../test-data/sample-elm-package.json");
+
+    /// Parsing into our model and back shouldn't lose or change anything.
+    fn assert_round_trips(json: &str) {
+        let elm_json: ElmJson = serde_json::from_str(json).unwrap();
+        assert_eq!(to_elm_style_string(&elm_json).unwrap(), json);
+    }
+
+    #[test]
+    fn test_reading_application_json() {
+        let val: ElmJson = serde_json::from_str(SAMPLE_APPLICATION_JSON).unwrap();
+        match val {
+            ElmJson::Application(application) => {
+                assert_eq!(application.dependencies.direct.len(), 12);
+                assert_eq!(application.dependencies.indirect.len(), 11);
+            }
+            ElmJson::Package(_) => panic!("should be an application"),
+        }
+        assert_round_trips(SAMPLE_APPLICATION_JSON);
+    }
+
+    #[test]
+    fn test_reading_package_json() {
+        let val: ElmJson = serde_json::from_str(SAMPLE_PACKAGE_JSON).unwrap();
+        match val {
+            ElmJson::Package(package) => {
+                assert_eq!(package.name, "KevinMGranger/elm-func-accessors");
+                assert_eq!(
+                    package.exposed_modules,
+                    ExposedModules::List(vec!["FuncAccessors".to_owned()])
+                );
+            }
+            ElmJson::Application(_) => panic!("should be a package"),
+        }
+        assert_round_trips(SAMPLE_PACKAGE_JSON);
+    }
+
+    #[test]
+    fn test_unknown_fields_are_kept() {
+        let with_notes =
+            SAMPLE_APPLICATION_JSON.replacen("\n}\n", ",\n    \"notes\": \"ours\"\n}\n", 1);
+        assert_round_trips(&with_notes);
+
+        let original: serde_json::Value =
+            serde_json::from_str(SAMPLE_PACKAGE_WITH_SOURCE_DIRS_JSON).unwrap();
+        let elm_json: ElmJson = serde_json::from_str(SAMPLE_PACKAGE_WITH_SOURCE_DIRS_JSON).unwrap();
+        assert_eq!(serde_json::to_value(&elm_json).unwrap(), original);
+        assert_eq!(
+            NormalizedElmJson::from(elm_json)
+                .other_fields
+                .get("source-directories"),
+            Some(&serde_json::json!(["src"]))
+        );
+    }
+
+    #[test]
+    fn test_misspelled_vendored_details_are_an_error() {
+        let json = r#"[{"path": "vendor/b", "revison": "1.2.0"}]"#;
+        let err = serde_json::from_str::<Vec<VendoredPackage>>(json).unwrap_err();
+        assert!(err.to_string().contains("unknown field `revison`"));
+    }
+
+    #[test]
+    fn test_named_patterns_are_an_error() {
+        let json = r#"[{"path": "vendor/*", "name": "someone/else"}]"#;
+        let err = serde_json::from_str::<Vec<VendoredPackage>>(json).unwrap_err();
+        assert!(err.to_string().contains("can't have a name"));
+
+        let json = r#"[{"path": "vendor/*", "source": "https://example.com/vendor.git"}]"#;
+        assert!(serde_json::from_str::<Vec<VendoredPackage>>(json).is_ok());
+    }
+
+    #[test]
+    fn test_vendored_packages_can_be_paths_or_objects() {
+        let json = r#"["vendor/a", {"path": "vendor/b", "name": "someone/b", "notes": "patched"}]"#;
+        let vendored: Vec<VendoredPackage> = serde_json::from_str(json).unwrap();
+        assert_eq!(vendored[0], VendoredPackage::at("vendor/a"));
+        assert_eq!(
+            vendored[1],
+            VendoredPackage {
+                name: Some("someone/b".to_owned()),
+                notes: Some("patched".to_owned()),
+                ..VendoredPackage::at("vendor/b")
+            }
+        );
+    }
+
+    #[test]
+    fn test_vendored_packages_stay_paths_without_details() {
+        let plain = vec![VendoredPackage::at("vendor/a")];
+        let detailed = vec![
+            VendoredPackage::at("vendor/a"),
+            VendoredPackage {
+                revision: Some("v1.2.0".to_owned()),
+                ..VendoredPackage::at("vendor/b")
+            },
+        ];
+
+        assert_eq!(serde_json::to_string(&plain).unwrap(), r#"["vendor/a"]"#);
+        assert_eq!(
+            serde_json::to_string(&detailed).unwrap(),
+            r#"["vendor/a",{"path":"vendor/b","revision":"v1.2.0"}]"#
+        );
+    }
+
+    #[test]
+    fn test_grouped_exposed_modules_keep_their_order() {
+        let json = r#"{"Zebras": ["Zebra"], "Aardvarks": ["Aardvark", "Anteater"]}"#;
+        let modules: ExposedModules = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            modules,
+            ExposedModules::Grouped(vec![
+                ("Zebras".to_owned(), vec!["Zebra".to_owned()]),
+                (
+                    "Aardvarks".to_owned(),
+                    vec!["Aardvark".to_owned(), "Anteater".to_owned()]
+                ),
+            ])
+        );
+        assert_eq!(
+            serde_json::to_string(&modules).unwrap(),
+            r#"{"Zebras":["Zebra"],"Aardvarks":["Aardvark","Anteater"]}"#
+        );
+    }
+}
@@ -0,0 +1,245 @@
+///! Rewriting elm-vendor.toml without losing what the user wrote in it.
+///!
+///! Serializing the config from scratch would throw away comments and layout,
+///! so instead we serialize it fresh, and then carry over only the parts that changed
+///! into the document the user already has.
+use toml_edit::{Array, ArrayOfTables, Document, Item, Table, TableLike, TomlError, Value};
+
+/// Make `previous` hold the same data as `updated`,
+/// keeping the comments and formatting of everything that didn't change.
+pub(crate) fn update_document(previous: &str, updated: &str) -> Result<String, TomlError> {
+    let mut document: Document = previous.parse()?;
+    let updated: Document = updated.parse()?;
+
+    merge_table(document.as_table_mut(), updated.as_table());
+
+    Ok(document.to_string())
+}
+
+fn merge_table(old: &mut dyn TableLike, new: &dyn TableLike) {
+    let removed: Vec<String> = old
+        .iter()
+        .map(|(key, _)| key.to_owned())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in removed {
+        old.remove(&key);
+    }
+
+    for (key, new_item) in new.iter() {
+        match old.get_mut(key) {
+            Some(old_item) => merge_item(old_item, new_item),
+            None => {
+                old.insert(key, new_item.clone());
+            }
+        }
+    }
+}
+
+fn merge_item(old: &mut Item, new: &Item) {
+    if let (Some(old), Some(new)) = (old.as_table_like_mut(), new.as_table_like()) {
+        return merge_table(old, new);
+    }
+    if let (Some(old), Some(new)) = (old.as_array_of_tables_mut(), new.as_array_of_tables()) {
+        return merge_array_of_tables(old, new);
+    }
+    // the user might have written inline what we'd write as a table
+    if let (Some(old), Ok(new)) = (old.as_value_mut(), new.clone().into_value()) {
+        return merge_value(old, &new);
+    }
+
+    *old = new.clone();
+}
+
+fn merge_value(old: &mut Value, new: &Value) {
+    if same_value(old, new) {
+        return;
+    }
+
+    match (old, new) {
+        (Value::Array(old), Value::Array(new)) => merge_array(old, new),
+        (Value::InlineTable(old), Value::InlineTable(new)) => merge_table(old, new),
+        (old, new) => {
+            // keep whatever comment was hanging off of the old value
+            let decor = old.decor().clone();
+            *old = new.clone();
+            *old.decor_mut() = decor;
+        }
+    }
+}
+
+/// Elements that are still around keep their formatting (and the comments in front of them),
+/// even if they moved.
+fn merge_array(old: &mut Array, new: &Array) {
+    let mut previous: Vec<Option<Value>> = old.iter().cloned().map(Some).collect();
+    let first = old.get(0).cloned();
+    if previous.is_empty() {
+        // an empty array has no layout worth keeping, so take the new one's
+        old.set_trailing(new.trailing());
+        old.set_trailing_comma(new.trailing_comma());
+    }
+    old.clear();
+
+    for (index, new_value) in new.iter().enumerate() {
+        let unchanged = previous
+            .iter_mut()
+            .find(|value| matches!(value, Some(value) if same_value(value, new_value)));
+        let value = match unchanged {
+            Some(value) => value.take().unwrap(),
+            // otherwise, treat it as an edit of whatever used to be in that spot
+            None => match previous.get_mut(index).and_then(Option::take) {
+                Some(mut value) => {
+                    merge_value(&mut value, new_value);
+                    value
+                }
+                None => laid_out_like(new_value, old.iter().last().or(first.as_ref())),
+            },
+        };
+        old.push_formatted(value);
+    }
+}
+
+/// A new array element, on a line of its own if `neighbor` is.
+fn laid_out_like(value: &Value, neighbor: Option<&Value>) -> Value {
+    let indent = neighbor
+        .and_then(|neighbor| neighbor.decor().prefix())
+        .and_then(|prefix| prefix.rfind('\n').map(|newline| &prefix[newline..]));
+    match indent {
+        // just the line break and indentation, not any comment in front of the neighbor
+        Some(indent) => value.clone().decorated(indent, ""),
+        None => value.clone(),
+    }
+}
+
+/// Like `merge_array`, but for `[[tables]]`.
+fn merge_array_of_tables(old: &mut ArrayOfTables, new: &ArrayOfTables) {
+    let mut previous: Vec<Option<Table>> = old.iter().cloned().map(Some).collect();
+    old.clear();
+
+    for (index, new_table) in new.iter().enumerate() {
+        let unchanged = previous
+            .iter_mut()
+            .find(|table| matches!(table, Some(table) if same_table(table, new_table)));
+        let table = match unchanged {
+            Some(table) => table.take().unwrap(),
+            None => match previous.get_mut(index).and_then(Option::take) {
+                Some(mut table) => {
+                    merge_table(&mut table, new_table);
+                    table
+                }
+                None => unpositioned(new_table),
+            },
+        };
+        old.push(table);
+    }
+}
+
+/// A copy of a table from the freshly serialized document,
+/// without its position in that document, so it lands after the table before it in ours.
+fn unpositioned(table: &Table) -> Table {
+    let mut copy = Table::new();
+    for (key, item) in table.iter() {
+        copy.insert(key, item.clone());
+    }
+    copy
+}
+
+fn same_item(a: &Item, b: &Item) -> bool {
+    match (a, b) {
+        (Item::Value(a), Item::Value(b)) => same_value(a, b),
+        (Item::Table(a), Item::Table(b)) => same_table(a, b),
+        (Item::ArrayOfTables(a), Item::ArrayOfTables(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_table(a, b))
+        }
+        _ => false,
+    }
+}
+
+fn same_table(a: &dyn TableLike, b: &dyn TableLike) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|(key, a)| matches!(b.get(key), Some(b) if same_item(a, b)))
+}
+
+/// Are these the same value, regardless of how they're written?
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value() == b.value(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| same_value(a, b))
+        }
+        (Value::InlineTable(a), Value::InlineTable(b)) => same_table(a, b),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMENTED: &str = r#"# vendored until upstream merges our fix
+vendored = [
+    # see #42
+    "vendor/elm-foo",
+]
+source-directories = ["src"]
+type = "application"
+
+[main-dependencies]
+# pinned, 1.0.5 breaks our ports
+"elm/core" = "1.0.4"
+"elm/html" = "1.0.0"
+
+[extras]
+elm-version = "0.19.1"
+"#;
+
+    #[test]
+    fn test_unchanged_document_is_untouched() {
+        assert_eq!(update_document(COMMENTED, COMMENTED).unwrap(), COMMENTED);
+    }
+
+    #[test]
+    fn test_comments_survive_edits() {
+        let updated = r#"vendored = ["vendor/elm-foo", "vendor/elm-bar"]
+source-directories = ["src"]
+type = "application"
+
+[main-dependencies]
+"elm/core" = "1.0.4"
+"elm/json" = "1.1.3"
+
+[extras]
+elm-version = "0.19.1"
+"#;
+        let expected = r#"# vendored until upstream merges our fix
+vendored = [
+    # see #42
+    "vendor/elm-foo",
+    "vendor/elm-bar",
+]
+source-directories = ["src"]
+type = "application"
+
+[main-dependencies]
+# pinned, 1.0.5 breaks our ports
+"elm/core" = "1.0.4"
+"elm/json" = "1.1.3"
+
+[extras]
+elm-version = "0.19.1"
+"#;
+        assert_eq!(update_document(COMMENTED, updated).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_one_line_arrays_stay_on_one_line() {
+        let previous = "vendored = [\"vendor/elm-foo\"]\n";
+        let updated = "vendored = [\"vendor/elm-foo\", \"vendor/elm-bar\"]\n";
+        assert_eq!(update_document(previous, updated).unwrap(), updated);
+    }
+}
@@ -0,0 +1,147 @@
+///! Changing several files as a single step.
+///!
+///! Vendoring rewrites both elm.json and the elm-vendor config file,
+///! and installing has elm change elm.json before we touch anything.
+///! If a later step fails, everything is put back the way it was,
+///! instead of leaving the project half-updated.
+use crate::config::ConfigFormat;
+use crate::lockfile::LOCKFILE_NAME;
+use crate::utils::{MultiError, TryWithProgress};
+use anyhow::{Context, Result};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// A file as it was before the transaction started.
+struct Backup {
+    path: PathBuf,
+    /// `None` if it didn't exist yet.
+    contents: Option<Vec<u8>>,
+}
+
+impl Backup {
+    fn of(path: PathBuf) -> Result<Backup> {
+        let contents = match fs::read(&path) {
+            Ok(contents) => Some(contents),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Couldn't back up {}", path.display()))
+            }
+        };
+
+        Ok(Backup { path, contents })
+    }
+}
+
+impl super::CmdContext {
+    /// Every file a command might change (or create).
+    fn project_files(&self) -> Vec<PathBuf> {
+        let config_files = ConfigFormat::ALL
+            .iter()
+            .map(|format| self.root.join(format.file_name()));
+
+        std::iter::once(self.root.join("elm.json"))
+            .chain(config_files)
+            .chain(std::iter::once(self.root.join(LOCKFILE_NAME)))
+            .collect()
+    }
+
+    /// Run a command's steps while holding the project lock,
+    /// putting every file back the way it was if any of them fail.
+    pub(crate) fn transaction<T>(&self, steps: impl FnOnce() -> Result<T>) -> Result<T> {
+        let _lock = self.lock()?;
+        // a dry run doesn't change anything, so there's nothing to put back
+        if self.dry_run {
+            return steps();
+        }
+
+        let backups = self
+            .project_files()
+            .into_iter()
+            .map(Backup::of)
+            .collect::<Result<Vec<_>>>()?;
+
+        let err = match steps() {
+            Ok(outcome) => return Ok(outcome),
+            Err(err) => err,
+        };
+
+        let restored = backups
+            .iter()
+            .try_with_progress(|backup| self.restore(backup));
+        match restored {
+            Ok(_) => Err(err),
+            Err(restore_errs) => {
+                let mut errors = vec![err];
+                errors.extend(restore_errs);
+                Err(MultiError::from(errors).into())
+            }
+        }
+    }
+
+    /// Put a file back the way it was, if it changed.
+    fn restore(&self, backup: &Backup) -> Result<()> {
+        let current = match fs::read(&backup.path) {
+            Ok(current) => Some(current),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        if current == backup.contents {
+            return Ok(());
+        }
+
+        match &backup.contents {
+            Some(contents) => self.write_atomically(&backup.path, contents),
+            None => Ok(fs::remove_file(&backup.path)?),
+        }
+        .with_context(|| format!("Couldn't put back {}", backup.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::CmdContext;
+    use std::fs;
+
+    #[test]
+    fn test_failed_steps_are_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = CmdContext {
+            yes: true,
+            root: dir.path().to_owned(),
+            dry_run: false,
+            elm_home: None,
+        };
+        let elm_json = dir.path().join("elm.json");
+        let elm_vendor_json = dir.path().join("elm-vendor.json");
+        fs::write(&elm_json, "before").unwrap();
+
+        let outcome: anyhow::Result<()> = ctx.transaction(|| {
+            fs::write(&elm_json, "after")?;
+            fs::write(&elm_vendor_json, "new")?;
+            anyhow::bail!("elm install failed")
+        });
+
+        assert_eq!(outcome.unwrap_err().to_string(), "elm install failed");
+        assert_eq!(fs::read_to_string(&elm_json).unwrap(), "before");
+        assert!(!elm_vendor_json.exists());
+    }
+
+    #[test]
+    fn test_successful_steps_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = CmdContext {
+            yes: true,
+            root: dir.path().to_owned(),
+            dry_run: false,
+            elm_home: None,
+        };
+        let elm_json = dir.path().join("elm.json");
+        fs::write(&elm_json, "before").unwrap();
+
+        ctx.transaction(|| Ok(fs::write(&elm_json, "after")?))
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&elm_json).unwrap(), "after");
+    }
+}
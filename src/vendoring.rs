@@ -1,11 +1,18 @@
 ///! Vendoring operations.
 
+use crate::check::ElmJsonDiff;
+use crate::elm_home;
+use crate::report::Report;
+use crate::resolver::*;
 use crate::shared::*;
 use crate::utils::*;
 use crate::version::*;
-use anyhow::{ensure, Context, Result};
-use ranges::{GenericRange, Ranges};
-use std::collections::HashMap;
+use crate::writer::to_elm_style_string;
+use anyhow::{anyhow, ensure, Context, Result};
+use ranges::{Domain, GenericRange, Ranges};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use std::fmt::{self, Display, Formatter};
 use std::fs;
@@ -20,20 +27,19 @@ use thiserror::Error;
 
 #[derive(Debug)]
 pub(crate) enum SerdeError {
-    #[allow(dead_code)]
     Toml(toml::de::Error),
     Json(serde_json::Error),
 }
 
 /// Convenience trait to attach a dependency name to an error.
-trait WithDepName: Sized {
+pub(crate) trait WithDepName: Sized {
     fn with_name_conv(self, name: impl ToString) -> VendorChangeError {
         self.with_name(name.to_string())
     }
     fn with_name(self, name: String) -> VendorChangeError;
 }
 
-trait WithDepNameExt<T> {
+pub(crate) trait WithDepNameExt<T> {
     fn with_name(self, name: impl ToString) -> Result<T, VendorChangeError>;
 }
 impl<T, E: WithDepName> WithDepNameExt<T> for Result<T, E> {
@@ -61,17 +67,36 @@ impl Display for SerdeError {
 #[derive(Error, Debug)]
 pub(crate) enum VendorChangeError {
     /// Various vendored packages requested incompatible
-    /// versions of a dependency.
+    /// versions of a dependency, with no version that they all allow.
     ConflictingDependency {
         dependency: String,
         versions: VersionsWithSources,
     },
     /// The vendored package didn't have an elm.json!
     NoElmJsonFound(String),
+    /// The vendored package's elm.json has a different name than elm-vendor.json says it should.
+    MisnamedPackage {
+        path: String,
+        expected: String,
+        found: Option<String>,
+    },
     /// Computer broke
     IoError(String, io::Error),
     /// The structure of a file wasn't quite what we expected.
     SerdeError(String, SerdeError),
+    /// Applications need an exact version of every dependency,
+    /// but all we could come up with was a range.
+    UnpinnedDependency(String, VersionRange),
+    /// There's no set of versions that makes every package happy.
+    Unresolvable(Explanation),
+    /// The resolver gave up for some reason other than a conflict.
+    ResolverFailed(String),
+}
+
+impl WithDepName for toml::de::Error {
+    fn with_name(self, name: String) -> VendorChangeError {
+        VendorChangeError::SerdeError(name, SerdeError::Toml(self))
+    }
 }
 
 impl WithDepName for serde_json::Error {
@@ -111,11 +136,27 @@ impl Display for VendorChangeError {
                 for (source, version) in versions.iter() {
                     writeln!(f, "\t{} wanted {}", source, version)?;
                 }
-                writeln!(f, "These need to be identical because I'm not smart enough to resolve a version that works for all of them. You may be able to override this soon.")?;
+                writeln!(f, "There's no version that all of these allow.")?;
             }
             NoElmJsonFound(dep) => {
                 writeln!(f, "There was no elm.json found for the package {}", dep)?
             }
+            MisnamedPackage {
+                path,
+                expected,
+                found,
+            } => match found {
+                Some(found) => writeln!(
+                    f,
+                    "elm-vendor.json says {} is {}, but its elm.json says it's {}",
+                    path, expected, found
+                )?,
+                None => writeln!(
+                    f,
+                    "elm-vendor.json says {} is {}, but its elm.json isn't for a package",
+                    path, expected
+                )?,
+            },
             IoError(dep, err) => writeln!(
                 f,
                 "There was an IO error while reading the elm json for {}: {}",
@@ -124,6 +165,16 @@ impl Display for VendorChangeError {
             SerdeError(dep, e) => {
                 writeln!(f, "There was a (de)serialization error for {}: {}", dep, e)?
             }
+            UnpinnedDependency(dep, range) => writeln!(
+                f,
+                "The dependency {} could only be narrowed down to {}, but applications need an exact version",
+                dep, range
+            )?,
+            Unresolvable(explanation) => {
+                writeln!(f, "There's no set of versions that works for every package:")?;
+                write!(f, "{}", explanation)?
+            }
+            ResolverFailed(err) => writeln!(f, "Couldn't resolve versions: {}", err)?,
         }
         Ok(())
     }
@@ -136,6 +187,9 @@ type VersionsWithSources = HashMap<String, DependencyVersion>;
 /// dependency name to Source, Value
 type DependenciesWithSources = HashMap<String, VersionsWithSources>;
 
+/// What the dependencies in elm-vendor.json are attributed to.
+const MAIN_PACKAGE_SOURCE: &str = "main package (elm-vendor.json)";
+
 struct Dependency {
     /// The package name that this dependency came from
     source: String,
@@ -145,22 +199,195 @@ struct Dependency {
     version: DependencyVersion,
 }
 
-/// Merge all dependency specifications into one if they're identical.
-fn collapse_sources(
-    sources: VersionsWithSources,
-) -> Result<DependencyVersion, VersionsWithSources> {
-    let ranges: Ranges<SemVer> = sources
+/// An override from elm-vendor.json that was used instead of what some packages asked for.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppliedOverride {
+    pub(crate) dependency: String,
+    pub(crate) version: DependencyVersion,
+    /// What each package asked for instead.
+    /// Besides vendored packages, this can be an elm package (at a version) that one of them uses.
+    pub(crate) instead_of: BTreeMap<String, DependencyVersion>,
+}
+
+impl Display for AppliedOverride {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let instead_of: Vec<String> = self
+            .instead_of
+            .iter()
+            .map(|(package, version)| format!("{} from {}", version, package))
+            .collect();
+        write!(
+            f,
+            "{} {} instead of {}",
+            self.dependency,
+            self.version,
+            instead_of.join(", ")
+        )
+    }
+}
+
+/// An override from elm-vendor.json that's only for a package that isn't vendored,
+/// so nothing used it.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct UnusedOverride {
+    pub(crate) dependency: String,
+    pub(crate) package: String,
+}
+
+impl Display for UnusedOverride {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is only for {}, which isn't vendored",
+            self.dependency, self.package
+        )
+    }
+}
+
+/// The overrides that are only for a package that isn't among the (expanded) vendored ones.
+fn unused_overrides(
+    overrides: &BTreeMap<String, VersionOverride>,
+    vendored: &[VendoredPackage],
+) -> Vec<UnusedOverride> {
+    overrides
+        .iter()
+        .filter_map(|(dependency, version_override)| match version_override {
+            VersionOverride::Only { package, .. }
+                if !vendored
+                    .iter()
+                    .any(|vendored_pkg| version_override.applies_to(&vendored_pkg.path)) =>
+            {
+                Some(UnusedOverride {
+                    dependency: dependency.clone(),
+                    package: package.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Combine the records of single uses of the same override.
+pub(crate) fn group_applied_overrides(
+    applied: impl IntoIterator<Item = AppliedOverride>,
+) -> Vec<AppliedOverride> {
+    let mut grouped: BTreeMap<String, AppliedOverride> = BTreeMap::new();
+    for applied in applied {
+        match grouped.get_mut(&applied.dependency) {
+            Some(existing) => existing.instead_of.extend(applied.instead_of),
+            None => {
+                grouped.insert(applied.dependency.clone(), applied);
+            }
+        }
+    }
+    grouped.into_values().collect()
+}
+
+/// Use the overrides from elm-vendor.json for what a vendored package asks for.
+fn apply_overrides(
+    dependencies: &mut [Dependency],
+    overrides: &BTreeMap<String, VersionOverride>,
+) -> Vec<AppliedOverride> {
+    let mut applied = Vec::new();
+    for dependency in dependencies {
+        let version = match overrides.get(&dependency.dependency) {
+            Some(version_override) if version_override.applies_to(&dependency.source) => {
+                version_override.version()
+            }
+            _ => continue,
+        };
+        if *version == dependency.version {
+            continue;
+        }
+
+        applied.push(AppliedOverride {
+            dependency: dependency.dependency.clone(),
+            version: version.clone(),
+            instead_of: vec![(dependency.source.clone(), dependency.version.clone())]
+                .into_iter()
+                .collect(),
+        });
+        dependency.version = version.clone();
+    }
+    applied
+}
+
+/// Merge all dependency specifications into the range of versions every one of them allows,
+/// for a package's elm.json.
+///
+/// Packages can only depend on ranges, so if a source pins an exact version
+/// (and everyone else allows it), we get the range of just that version.
+/// When nothing is allowed by all of them, only the fewest sources that disagree are given back.
+fn collapse_sources(sources: VersionsWithSources) -> Result<VersionRange, VersionsWithSources> {
+    let allowed = sources
         .values()
-        .map(|version| version.conv::<GenericRange<SemVer>>())
-        .collect();
-    let range_slice = ranges.as_slice();
-    if range_slice.len() != 1 {
-        Err(sources)
-    } else {
-        Ok(range_slice[0].try_into().unwrap())
+        .fold(Ranges::full(), |allowed: Ranges<SemVer>, version| {
+            allowed.intersect(version.conv::<GenericRange<SemVer>>())
+        });
+
+    match allowed.as_slice() {
+        [range] => Ok(match (*range).try_into().unwrap() {
+            DependencyVersion::SpecificVersion(version) => VersionRange {
+                lower: version,
+                lower_relation: Relation::LTE,
+                higher_relation: Relation::LT,
+                higher: version
+                    .successor()
+                    .expect("no version comes after the highest version"),
+            },
+            DependencyVersion::VersionRange(range) => range,
+        }),
+        _ => Err(minimal_conflict(sources)),
     }
 }
 
+/// The smallest group of sources that can't agree on a version.
+///
+/// Version ranges are intervals, so if all of them together don't overlap,
+/// there's always a pair (or a single, empty range) that doesn't either.
+fn minimal_conflict(mut sources: VersionsWithSources) -> VersionsWithSources {
+    let mut names: Vec<&String> = sources.keys().collect();
+    names.sort();
+    let range_of = |name: &String| (&sources[name]).conv::<GenericRange<SemVer>>();
+
+    let conflict: Vec<String> = match names.iter().find(|name| range_of(name).is_empty()) {
+        Some(name) => vec![(*name).clone()],
+        None => names
+            .iter()
+            .enumerate()
+            .flat_map(|(i, a)| names[i + 1..].iter().map(move |b| (*a, *b)))
+            .find(|(a, b)| Ranges::from(range_of(a)).intersect(range_of(b)).is_empty())
+            .map(|(a, b)| vec![a.clone(), b.clone()])
+            // we only get here when there's a conflict, but just in case, blame everyone
+            .unwrap_or_default(),
+    };
+
+    if !conflict.is_empty() {
+        sources.retain(|source, _| conflict.contains(source));
+    }
+    sources
+}
+
+/// Where `dir` is relative to the project `root`, both canonicalized,
+/// or `None` if it isn't a directory inside the project.
+fn inside_project<'a>(root: &Path, dir: &'a Path) -> Option<&'a Path> {
+    match dir.strip_prefix(root) {
+        Ok(relative) if relative != Path::new("") => Some(relative),
+        _ => None,
+    }
+}
+
+/// How a path relative to the project root is written in elm-vendor.json,
+/// which is the same on every OS.
+pub(crate) fn to_vendored_path(relative: &Path) -> Result<String> {
+    Ok(relative
+        .to_str()
+        .with_context(|| format!("{} isn't valid unicode", relative.display()))?
+        .replace(std::path::MAIN_SEPARATOR, "/"))
+}
+
 /// Take the flattened list of dependencies with sources
 /// and create a dict with it.
 fn coalesce_dependencies(
@@ -181,6 +408,23 @@ fn coalesce_dependencies(
     }
 }
 
+/// Group the flattened list of dependencies by the package that asked for them.
+fn requirements_by_source(dependencies: impl IntoIterator<Item = Dependency>) -> Requirements {
+    let mut requirements = Requirements::new();
+    for Dependency {
+        source,
+        dependency,
+        version,
+    } in dependencies
+    {
+        requirements
+            .entry(source)
+            .or_default()
+            .insert(dependency, version);
+    }
+    requirements
+}
+
 impl NormalizedElmJson {
     /// extract all paths to source dirs, contextualized by the package's name
     fn contextualize_source_dirs<'a>(
@@ -192,6 +436,53 @@ impl NormalizedElmJson {
             .map(move |source_dir| name.join(source_dir))
     }
 
+    /// Turn this back into the structure of an elm.json file.
+    ///
+    /// Applications can only depend on exact versions,
+    /// so any dependency that's still a range is an error.
+    fn into_elm_json(self) -> Result<ElmJson, Vec<anyhow::Error>> {
+        let mut fields: serde_json::Map<String, serde_json::Value> =
+            self.other_fields.into_iter().collect();
+        fields.insert("type".to_owned(), json!(self.kind));
+
+        match self.kind {
+            ElmJsonKind::Application => {
+                let direct: BTreeMap<String, SemVer> = self
+                    .dependencies
+                    .into_iter()
+                    .try_with_progress(|(dependency, version)| match version {
+                        DependencyVersion::SpecificVersion(version) => Ok((dependency, version)),
+                        DependencyVersion::VersionRange(range) => Err(anyhow::Error::from(
+                            VendorChangeError::UnpinnedDependency(dependency, range),
+                        )),
+                    })?
+                    .into_iter()
+                    .collect();
+                let dependencies = ApplicationDeps {
+                    direct,
+                    indirect: self.indirect_dependencies,
+                };
+
+                fields.insert("source-directories".to_owned(), json!(self.source_dirs));
+                fields.insert("dependencies".to_owned(), json!(dependencies));
+            }
+            ElmJsonKind::Package => {
+                if self.source_dirs != [Path::new(PACKAGE_SOURCE_DIR)] {
+                    return Err(vec![anyhow!(
+                        "elm packages can only use {}/, so nothing can be vendored into them",
+                        PACKAGE_SOURCE_DIR
+                    )]);
+                }
+
+                fields.insert("dependencies".to_owned(), json!(self.dependencies));
+            }
+        }
+
+        serde_json::from_value(serde_json::Value::Object(fields))
+            .context("The fields kept in elm-vendor.json don't make a valid elm.json")
+            .map_err(|err| vec![err])
+    }
+
     fn dependencies_with_source_name<'a>(
         &'a self,
         package: &'a str,
@@ -216,77 +507,546 @@ impl NormalizedElmJson {
 impl super::CmdContext {
     pub(crate) fn load_package_for(
         &self,
-        package: &str,
+        package: &VendoredPackage,
     ) -> Result<NormalizedElmJson, VendorChangeError> {
-        let file =
-            fs::File::open(self.root.join(package).join("elm.json")).map_err(
-                |io_err| match io_err.kind() {
-                    io::ErrorKind::NotFound => {
-                        VendorChangeError::NoElmJsonFound(package.to_owned())
-                    }
-                    _ => io_err.with_name_conv(package),
-                },
-            )?;
-        let package_json: ElmJson = serde_json::from_reader(file).with_name(package.to_owned())?;
-        Ok(package_json.into())
-    }
-
-    pub fn vendor(&self) -> Result<()> {
-        let is_committed = self.check_if_elm_json_is_commited()?;
-
-        ensure!(is_committed, "elm.json is not committed!");
-        let elm_vendor_json_name = self
-            .find_elm_vendor_json()?
-            // TODO do we just run it for them?
-            .context("you must run elm-vendor init first")?;
-
-        let elm_vendor_json_file = fs::OpenOptions::new()
-            .read(true)
-            .open(self.root.join(elm_vendor_json_name))?;
-        let elm_vendor_json: ElmVendor = serde_json::from_reader(elm_vendor_json_file)?;
-        let is_lamdera_project = is_lamdera_project(&elm_vendor_json.main_deps);
-
-        let results: Vec<(Vec<PathBuf>, Vec<Dependency>)> = elm_vendor_json
-            .vendored
+        let path = &package.path;
+        let elm_json_path = self.root.join(path).join("elm.json");
+        let file = fs::File::open(elm_json_path).map_err(|io_err| match io_err.kind() {
+            io::ErrorKind::NotFound => VendorChangeError::NoElmJsonFound(path.clone()),
+            _ => io_err.with_name_conv(path),
+        })?;
+        let package_json: NormalizedElmJson = serde_json::from_reader::<_, ElmJson>(file)
+            .with_name(path)?
+            .into();
+
+        if let Some(expected) = &package.name {
+            let found = package_json
+                .other_fields
+                .get("name")
+                .and_then(serde_json::Value::as_str);
+            if found != Some(expected.as_str()) {
+                return Err(VendorChangeError::MisnamedPackage {
+                    path: path.clone(),
+                    expected: expected.clone(),
+                    found: found.map(str::to_owned),
+                });
+            }
+        }
+
+        Ok(package_json)
+    }
+
+    /// Replace every glob in the vendored list with the packages it matches,
+    /// which are the directories that have an elm.json.
+    ///
+    /// Matches are sorted, so the source directories we write don't shuffle around.
+    /// Details given for a glob, like its source, apply to every package it matches.
+    /// Every package has to be inside the project, even after following `..` and symlinks.
+    pub(crate) fn expand_vendored(
+        &self,
+        vendored: &[VendoredPackage],
+    ) -> Result<Vec<VendoredPackage>> {
+        let project = self.root.canonicalize()?;
+        let mut expanded: Vec<VendoredPackage> = Vec::new();
+        let mut push = |package: VendoredPackage| {
+            if !expanded
+                .iter()
+                .any(|seen| Path::new(&seen.path) == Path::new(&package.path))
+            {
+                expanded.push(package);
+            }
+        };
+
+        for package in vendored {
+            if !package.is_pattern() {
+                // a directory that doesn't exist is reported once we look for its elm.json
+                if let Ok(dir) = self.root.join(&package.path).canonicalize() {
+                    ensure!(
+                        inside_project(&project, &dir).is_some(),
+                        "{} isn't a directory inside the project",
+                        package.path
+                    );
+                }
+                push(package.clone());
+                continue;
+            }
+
+            let root = self
+                .root
+                .to_str()
+                .with_context(|| format!("{} isn't valid unicode", self.root.display()))?;
+            let pattern = format!("{}/{}", glob::Pattern::escape(root), package.path);
+            let mut matches = glob::glob(&pattern)
+                .with_context(|| format!("{} isn't a valid pattern", package.path))?
+                .collect::<Result<Vec<PathBuf>, _>>()?;
+            matches.sort();
+
+            for dir in matches {
+                if !dir.join("elm.json").is_file() {
+                    continue;
+                }
+                let dir = dir.canonicalize()?;
+                let relative = inside_project(&project, &dir).with_context(|| {
+                    format!(
+                        "{} matched {}, which isn't inside the project",
+                        package.path,
+                        dir.display()
+                    )
+                })?;
+                push(VendoredPackage {
+                    path: to_vendored_path(relative)?,
+                    ..package.clone()
+                });
+            }
+        }
+
+        Ok(expanded)
+    }
+
+    /// Combine the main package described by elm-vendor.json with the given vendored packages,
+    /// producing the contents elm.json should have,
+    /// which of the overrides in elm-vendor.json took, and which had nothing to apply to.
+    ///
+    /// The strategy decides which versions applications get when more than one would work.
+    pub(crate) fn merge_vendored(
+        &self,
+        elm_vendor_json: &ElmVendor,
+        vendored: &[VendoredPackage],
+        strategy: Strategy,
+    ) -> Result<(NormalizedElmJson, Vec<AppliedOverride>, Vec<UnusedOverride>)> {
+        // a package's dependencies get picked by each application that uses it,
+        // which won't know about our overrides
+        ensure!(
+            elm_vendor_json.kind == ElmJsonKind::Application || elm_vendor_json.overrides.is_empty(),
+            "Overrides only work in applications, but this is a package and elm-vendor.json overrides {}",
+            elm_vendor_json
+                .overrides
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let vendored = self.expand_vendored(vendored)?;
+        let unused_overrides = unused_overrides(&elm_vendor_json.overrides, &vendored);
+
+        let results: Vec<(Vec<PathBuf>, Vec<Dependency>)> = vendored
             .iter()
             .try_with_progress(|vendored_pkg| -> Result<_> {
                 let package_json = self.load_package_for(vendored_pkg)?;
 
-                let source_dirs = package_json.contextualize_source_dirs(vendored_pkg.as_ref());
+                let source_dirs =
+                    package_json.contextualize_source_dirs(vendored_pkg.path.as_ref());
 
-                let dependencies = package_json.dependencies_with_source_name(vendored_pkg);
+                let dependencies = package_json.dependencies_with_source_name(&vendored_pkg.path);
 
                 Ok((source_dirs.collect(), dependencies.collect()))
             })
             .map_err(MultiError::from)?;
-        let mut source_dirs = elm_vendor_json.source_dirs;
+        let mut source_dirs = elm_vendor_json.source_dirs.clone();
 
-        let mut dependencies = elm_vendor_json
+        let mut all_dependencies: Vec<Dependency> = elm_vendor_json
             .main_deps
-            .into_iter()
-            .map(|(dependency, version)| -> Result<_> {
-                let mut deps = HashMap::new();
-                deps.insert("main package (elm-vendor.json)".to_owned(), version);
-
-                Ok((dependency, deps))
+            .iter()
+            .map(|(dependency, version)| Dependency {
+                source: MAIN_PACKAGE_SOURCE.to_owned(),
+                dependency: dependency.clone(),
+                version: version.clone(),
             })
-            .collect::<Result<DependenciesWithSources, _>>()?;
+            .collect();
 
-        for (vendored_pkg_source_dirs, vendored_pkg_deps) in results {
+        let mut applied_overrides = Vec::new();
+        for (vendored_pkg_source_dirs, mut vendored_pkg_deps) in results {
             source_dirs.extend(vendored_pkg_source_dirs);
+            applied_overrides.extend(apply_overrides(
+                &mut vendored_pkg_deps,
+                &elm_vendor_json.overrides,
+            ));
+            all_dependencies.extend(vendored_pkg_deps);
+        }
+
+        let (dependencies, indirect_dependencies) = match elm_vendor_json.kind {
+            // applications need exact versions of everything, so let the solver pick them
+            ElmJsonKind::Application => {
+                let requirements = requirements_by_source(all_dependencies);
+
+                let elm_home = self.elm_home();
+                let mut available = versions_mentioned_in(&requirements);
+                extend_available(
+                    &mut available,
+                    elm_home::cached_versions(elm_home.as_deref())?,
+                );
+                match elm_home::read_registry(elm_home.as_deref())? {
+                    Some(registry) => extend_available(&mut available, registry),
+                    None => eprintln!(
+                        "elm hasn't cached the package registry yet, so only versions mentioned in elm.json files will be considered"
+                    ),
+                }
+
+                // overrides for every package also cover what the vendored packages depend on
+                let overrides: BTreeMap<String, DependencyVersion> = elm_vendor_json
+                    .overrides
+                    .iter()
+                    .filter(|(_, version_override)| {
+                        matches!(version_override, VersionOverride::Everywhere(_))
+                    })
+                    .map(|(dependency, version_override)| {
+                        (dependency.clone(), version_override.version().clone())
+                    })
+                    .collect();
+
+                // elm-vendor.lock is what was picked last time, even on another machine
+                let locked = match (strategy, self.read_lockfile()?) {
+                    (Strategy::Locked, Some(lockfile)) => lockfile.dependencies,
+                    (Strategy::Locked, None) => self.read_elm_json()?.pinned_versions(),
+                    (Strategy::Newest, _) | (Strategy::Minimal, _) => BTreeMap::new(),
+                };
+
+                let (resolved, transitively_applied) = resolve_versions(
+                    &requirements,
+                    &available,
+                    &overrides,
+                    strategy,
+                    &locked,
+                    elm_home.as_deref(),
+                )?;
+                applied_overrides.extend(transitively_applied);
+
+                let (direct, indirect): (BTreeMap<_, _>, BTreeMap<_, _>) =
+                    resolved.into_iter().partition(|(dependency, _)| {
+                        requirements
+                            .values()
+                            .any(|dependencies| dependencies.contains_key(dependency))
+                    });
+
+                let direct = direct
+                    .into_iter()
+                    .map(|(dependency, version)| (dependency, version.into()))
+                    .collect();
+                (direct, indirect)
+            }
+            ElmJsonKind::Package => {
+                let mut dependencies = DependenciesWithSources::new();
+                coalesce_dependencies(all_dependencies, &mut dependencies);
 
-            coalesce_dependencies(vendored_pkg_deps, &mut dependencies)
+                let dependencies = dependencies
+                    .into_iter()
+                    .try_with_progress(|(dependency, sources)| -> Result<_> {
+                        let range = collapse_sources(sources).with_name(&dependency)?;
+                        Ok((dependency, DependencyVersion::VersionRange(range)))
+                    })
+                    .map_err(MultiError::from)
+                    .map(BTreeMap::from_iter)?;
+                (dependencies, BTreeMap::new())
+            }
+        };
+
+        let elm_json = NormalizedElmJson {
+            kind: elm_vendor_json.kind,
+            source_dirs,
+            dependencies,
+            indirect_dependencies,
+            other_fields: elm_vendor_json.extras.clone(),
+        };
+        Ok((
+            elm_json,
+            group_applied_overrides(applied_overrides),
+            unused_overrides,
+        ))
+    }
+
+    /// Overwrite elm.json with the given contents,
+    /// returning what changed.
+    pub(crate) fn write_elm_json(&self, elm_json: NormalizedElmJson) -> Result<ElmJsonDiff> {
+        let changes = ElmJsonDiff::between(&self.read_elm_json()?, &elm_json);
+        let elm_json = elm_json.into_elm_json().map_err(MultiError::from)?;
+
+        self.write_file(
+            &self.root.join("elm.json"),
+            &to_elm_style_string(&elm_json)?,
+        )?;
+
+        Ok(changes)
+    }
+
+    /// Rebuild elm.json from elm-vendor.json and every vendored package,
+    /// first adding the given package to the vendored list if there is one.
+    pub fn vendor(&self, package: Option<&str>, strategy: Strategy) -> Result<Report> {
+        self.transaction(|| {
+            let mut elm_vendor_json = self.read_elm_vendor()?;
+            let mut elm_vendor_changed = false;
+
+            // committing first is only a safety net for when we actually write
+            if !self.dry_run {
+                let is_committed = self.check_if_elm_json_is_commited()?;
+                ensure!(is_committed, "elm.json is not committed!");
+
+                // only the first time, since afterwards what's committed is already vendored
+                if elm_vendor_json.pre_vendor_elm_json.is_none() {
+                    elm_vendor_json.pre_vendor_elm_json =
+                        Some(self.committed_elm_json()?.to_string());
+                    elm_vendor_changed = true;
+                }
+            }
+
+            if let Some(package) = package {
+                let package = self.new_vendored_package(&elm_vendor_json, package)?;
+                elm_vendor_json.vendored.push(package);
+                elm_vendor_changed = true;
+            }
+
+            // only touch elm-vendor.json once we know everything actually fits together
+            let (elm_json, overrides, unused_overrides) =
+                self.merge_vendored(&elm_vendor_json, &elm_vendor_json.vendored, strategy)?;
+            let lockfile = self.lockfile_for(&elm_json, &elm_vendor_json.vendored)?;
+            if elm_vendor_changed {
+                self.write_elm_vendor(&elm_vendor_json)?;
+            }
+
+            let changes = self.write_elm_json(elm_json)?;
+            self.write_lockfile(&lockfile)?;
+            Ok(Report::Rebuilt {
+                changes,
+                overrides,
+                unused_overrides,
+                dry_run: self.dry_run,
+            })
+        })
+    }
+
+    /// Make sure the given directory can be vendored,
+    /// and give back its path relative to the project root.
+    fn new_vendored_package(
+        &self,
+        elm_vendor_json: &ElmVendor,
+        package: &str,
+    ) -> Result<VendoredPackage> {
+        let root = self.root.canonicalize()?;
+        let dir = self
+            .root
+            .join(package)
+            .canonicalize()
+            .with_context(|| format!("Couldn't find the directory {}", package))?;
+        let relative = inside_project(&root, &dir)
+            .with_context(|| format!("{} isn't a directory inside the project", package))?;
+        let relative = to_vendored_path(relative)?;
+
+        ensure!(
+            !self
+                .expand_vendored(&elm_vendor_json.vendored)?
+                .iter()
+                .any(|vendored_pkg| Path::new(&vendored_pkg.path) == Path::new(&relative)),
+            "{} is already vendored",
+            relative
+        );
+
+        let package = VendoredPackage::at(relative);
+        self.load_package_for(&package)?;
+        Ok(package)
+    }
+
+    /// Rebuild elm.json from elm-vendor.json,
+    /// either without any vendored packages or without just the given one.
+    ///
+    /// Removing a single package also takes it out of the vendored list.
+    pub fn unvendor(&self, package: Option<&str>) -> Result<Report> {
+        let prompt = match package {
+            Some(_) => UNVENDOR_ONE_PROMPT,
+            None => UNVENDOR_ALL_PROMPT,
+        };
+        if !self.confirm(prompt)? {
+            return Ok(Report::NothingDone);
         }
+        self.transaction(|| {
+            let mut elm_vendor_json = self.read_elm_vendor()?;
+
+            let remaining = match package {
+                None => {
+                    if let Some((changes, restored)) =
+                        self.restore_pre_vendor_elm_json(&elm_vendor_json)?
+                    {
+                        elm_vendor_json.pre_vendor_elm_json = None;
+                        self.write_elm_vendor(&elm_vendor_json)?;
+                        self.write_lockfile(&self.lockfile_for(&restored, &[])?)?;
+
+                        return Ok(Report::Rebuilt {
+                            changes,
+                            overrides: Vec::new(),
+                            unused_overrides: unused_overrides(&elm_vendor_json.overrides, &[]),
+                            dry_run: self.dry_run,
+                        });
+                    }
+                    Vec::new()
+                }
+                Some(package) => {
+                    let vendored_count = elm_vendor_json.vendored.len();
+                    elm_vendor_json
+                        .vendored
+                        .retain(|vendored_pkg| Path::new(&vendored_pkg.path) != Path::new(package));
+                    if elm_vendor_json.vendored.len() == vendored_count {
+                        let matched_by_pattern = self
+                            .expand_vendored(&elm_vendor_json.vendored)?
+                            .iter()
+                            .any(|vendored_pkg| Path::new(&vendored_pkg.path) == Path::new(package));
+                        ensure!(
+                            !matched_by_pattern,
+                            "{} is vendored by a pattern in elm-vendor.json, so it can't be removed on its own",
+                            package
+                        );
+                        anyhow::bail!("{} is not a vendored package", package);
+                    }
+
+                    self.write_elm_vendor(&elm_vendor_json)?;
+                    elm_vendor_json.vendored.clone()
+                }
+            };
 
-        let dependencies: HashMap<String, DependencyVersion> = dependencies
-            .into_iter()
-            .try_with_progress(|(dependency, sources)| -> Result<_> {
-                let version = collapse_sources(sources).with_name(&dependency)?;
-                Ok((dependency, version))
+            // keep what was picked before, rather than upgrading everything along the way
+            let (elm_json, overrides, unused_overrides) =
+                self.merge_vendored(&elm_vendor_json, &remaining, Strategy::Locked)?;
+            let lockfile = self.lockfile_for(&elm_json, &remaining)?;
+
+            let changes = self.write_elm_json(elm_json)?;
+            self.write_lockfile(&lockfile)?;
+            Ok(Report::Rebuilt {
+                changes,
+                overrides,
+                unused_overrides,
+                dry_run: self.dry_run,
             })
-            .map_err(MultiError::from)
-            .map(HashMap::from_iter)?;
+        })
+    }
 
-        Ok(())
+    /// Put back elm.json exactly as it was committed before vendoring,
+    /// returning what changed and what it is now.
+    ///
+    /// That's only right if nothing in elm-vendor.json about the main package has changed since:
+    /// its dependencies, source directories, or other fields, and no overrides apply to it.
+    /// Otherwise, or if the snapshot can't be found, we give up so elm.json gets rebuilt instead.
+    fn restore_pre_vendor_elm_json(
+        &self,
+        elm_vendor_json: &ElmVendor,
+    ) -> Result<Option<(ElmJsonDiff, NormalizedElmJson)>> {
+        let id = match &elm_vendor_json.pre_vendor_elm_json {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let contents = match git2::Oid::from_str(id)
+            .map_err(anyhow::Error::from)
+            .and_then(|id| self.read_blob(id))
+        {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!(
+                    "Couldn't find the elm.json from before vendoring ({:#}), so I'll rebuild it from elm-vendor.json instead",
+                    err
+                );
+                return Ok(None);
+            }
+        };
+        let snapshot: NormalizedElmJson = serde_json::from_slice::<ElmJson>(&contents)
+            .context("The elm.json from before vendoring isn't valid")?
+            .into();
+
+        let main_package_changed = snapshot.kind != elm_vendor_json.kind
+            || snapshot.dependencies != elm_vendor_json.main_deps
+            || snapshot.source_dirs != elm_vendor_json.source_dirs
+            || snapshot.other_fields != elm_vendor_json.extras;
+        if main_package_changed {
+            eprintln!(
+                "The main package changed since vendoring, so I'll rebuild elm.json from elm-vendor.json instead of restoring it"
+            );
+            return Ok(None);
+        }
+        // overrides for every package also cover what the main dependencies depend on,
+        // which the old elm.json knows nothing about
+        let has_overrides = elm_vendor_json
+            .overrides
+            .values()
+            .any(|version_override| matches!(version_override, VersionOverride::Everywhere(_)));
+        if has_overrides {
+            eprintln!(
+                "elm-vendor.json overrides versions, so I'll rebuild elm.json from it instead of restoring the old one"
+            );
+            return Ok(None);
+        }
+
+        let changes = ElmJsonDiff::between(&self.read_elm_json()?, &snapshot);
+        let contents = String::from_utf8(contents)
+            .context("The elm.json from before vendoring isn't valid UTF-8")?;
+        self.write_file(&self.root.join("elm.json"), &contents)?;
+
+        Ok(Some((changes, snapshot)))
+    }
+}
+
+const UNVENDOR_ALL_PROMPT: &str =
+    "I'm going to rebuild elm.json from elm-vendor.json, leaving out every vendored package.";
+const UNVENDOR_ONE_PROMPT: &str =
+    "I'm going to remove that package from elm-vendor.json and rebuild elm.json without it.";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(versions: &[(&str, &str)]) -> VersionsWithSources {
+        versions
+            .iter()
+            .map(|(source, version)| (source.to_string(), version.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_pinned_version_inside_ranges_wins() {
+        let collapsed = collapse_sources(sources(&[
+            (MAIN_PACKAGE_SOURCE, "1.0.5"),
+            ("vendor/a", "1.0.0 <= v < 2.0.0"),
+            ("vendor/b", "1.0.2 <= v < 2.0.0"),
+        ]));
+
+        assert_eq!(collapsed.unwrap().to_string(), "1.0.5 <= v < 1.0.6");
+    }
+
+    #[test]
+    fn test_pinned_main_dependency_becomes_a_range() {
+        let collapsed = collapse_sources(sources(&[(MAIN_PACKAGE_SOURCE, "1.0.5")]));
+
+        assert_eq!(collapsed.unwrap().to_string(), "1.0.5 <= v < 1.0.6");
+    }
+
+    #[test]
+    fn test_ranges_are_kept() {
+        let collapsed = collapse_sources(sources(&[(MAIN_PACKAGE_SOURCE, "1.0.0 <= v < 2.0.0")]));
+
+        assert_eq!(collapsed.unwrap().to_string(), "1.0.0 <= v < 2.0.0");
+    }
+
+    #[test]
+    fn test_overlapping_ranges_are_narrowed() {
+        let collapsed = collapse_sources(sources(&[
+            ("vendor/a", "1.0.0 <= v < 2.0.0"),
+            ("vendor/b", "1.2.0 <= v < 3.0.0"),
+        ]));
+
+        assert_eq!(collapsed.unwrap().to_string(), "1.2.0 <= v < 2.0.0");
+    }
+
+    #[test]
+    fn test_only_the_conflicting_sources_are_blamed() {
+        let collapsed = collapse_sources(sources(&[
+            (MAIN_PACKAGE_SOURCE, "1.0.0 <= v < 3.0.0"),
+            ("vendor/a", "1.0.0 <= v < 2.0.0"),
+            ("vendor/b", "2.0.0 <= v < 3.0.0"),
+            ("vendor/c", "1.5.0 <= v < 3.0.0"),
+        ]));
+
+        assert_eq!(
+            collapsed.unwrap_err(),
+            sources(&[
+                ("vendor/a", "1.0.0 <= v < 2.0.0"),
+                ("vendor/b", "2.0.0 <= v < 3.0.0"),
+            ])
+        );
     }
 }
@@ -9,7 +9,7 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{digit1, space0},
-    combinator::{map, map_res},
+    combinator::{all_consuming, map, map_res},
     error::ParseError,
     sequence::{delimited, tuple},
     IResult,
@@ -147,13 +147,13 @@ impl FromStr for SemVer {
     type Err = nom::error::Error<String>;
 
     fn from_str(str: &str) -> Result<Self, Self::Err> {
-        semver(str).finalize()
+        all_consuming(semver)(str).finalize()
     }
 }
 //endregion
 
 //region Relation
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Relation {
     LTE,
     LT,
@@ -170,7 +170,7 @@ impl Display for Relation {
 //endregion
 
 //region VersionRange
-#[derive(Debug, Clone)]
+#[derive(DeserializeFromStr, SerializeDisplay, Debug, Clone, PartialEq, Eq)]
 pub struct VersionRange {
     pub lower: SemVer,
     pub lower_relation: Relation,
@@ -235,13 +235,13 @@ impl FromStr for VersionRange {
     type Err = nom::error::Error<String>;
 
     fn from_str(str: &str) -> Result<Self, Self::Err> {
-        version_range(str).finalize()
+        all_consuming(version_range)(str).finalize()
     }
 }
 //endregion
 
 //region DepVersion
-#[derive(DeserializeFromStr, SerializeDisplay, Debug, Clone)]
+#[derive(DeserializeFromStr, SerializeDisplay, Debug, Clone, PartialEq, Eq)]
 pub enum DependencyVersion {
     SpecificVersion(SemVer),
     VersionRange(VersionRange),
@@ -292,7 +292,7 @@ impl FromStr for DependencyVersion {
     type Err = nom::error::Error<String>;
 
     fn from_str(str: &str) -> Result<DependencyVersion, Self::Err> {
-        version(str).finalize()
+        all_consuming(version)(str).finalize()
     }
 }
 //endregion
@@ -328,7 +328,8 @@ fn relation(input: &str) -> IResult<&str, Relation> {
     let lt = map(tag("<"), |_| Relation::LT);
     let lte = map(tag("<="), |_| Relation::LTE);
 
-    alt((lt, lte))(input)
+    // `<=` has to come first, otherwise `<` happily matches its first half
+    alt((lte, lt))(input)
 }
 
 fn version_variable(input: &str) -> IResult<&str, ()> {
@@ -360,3 +361,38 @@ fn version(input: &str) -> IResult<&str, DependencyVersion> {
     alt((range, semver))(input)
 }
 //endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_a_range_with_an_inclusive_lower_bound() {
+        let range: VersionRange = "1.0.0 <= v < 2.0.0".parse().unwrap();
+        assert_eq!(
+            range,
+            VersionRange {
+                lower: SemVer {
+                    major: 1,
+                    minor: 0,
+                    patch: 0,
+                },
+                lower_relation: Relation::LTE,
+                higher_relation: Relation::LT,
+                higher: SemVer {
+                    major: 2,
+                    minor: 0,
+                    patch: 0,
+                },
+            }
+        );
+        assert_eq!(range.to_string(), "1.0.0 <= v < 2.0.0");
+    }
+
+    #[test]
+    fn test_parsing_rejects_trailing_input() {
+        assert!("1.0.0 <= v < 2.0.0 oops".parse::<VersionRange>().is_err());
+        assert!("1.0.0.1".parse::<SemVer>().is_err());
+        assert!("1.0.0 garbage".parse::<DependencyVersion>().is_err());
+    }
+}
@@ -0,0 +1,49 @@
+///! Writing JSON config files exactly the way elm writes elm.json,
+///! so the diffs we leave behind are as small as possible.
+///!
+///! The order of the keys comes from the order of the fields in `shared`.
+use serde::Serialize;
+use serde_json::ser::{PrettyFormatter, Serializer};
+
+/// Serialize with 4 space indents and a trailing newline, like elm.
+pub(crate) fn to_elm_style_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    let mut bytes = Vec::new();
+    let mut serializer =
+        Serializer::with_formatter(&mut bytes, PrettyFormatter::with_indent(b"    "));
+    value.serialize(&mut serializer)?;
+    bytes.push(b'\n');
+
+    Ok(String::from_utf8(bytes).expect("serde_json only writes UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::ElmJson;
+    const SAMPLE_APPLICATION_JSON: &str = include_str!("../test-data/sample-elm-app.json");
+    const SAMPLE_PACKAGE_JSON: &str =
+        include_str!("../test-data/sample-elm-package-canonical.json");
+
+    fn rewrite(json: &str) -> String {
+        let elm_json: ElmJson = serde_json::from_str(json).unwrap();
+        to_elm_style_string(&elm_json).unwrap()
+    }
+
+    #[test]
+    fn test_application_is_written_like_elm_does() {
+        assert_eq!(rewrite(SAMPLE_APPLICATION_JSON), SAMPLE_APPLICATION_JSON);
+    }
+
+    #[test]
+    fn test_package_is_written_like_elm_does() {
+        assert_eq!(rewrite(SAMPLE_PACKAGE_JSON), SAMPLE_PACKAGE_JSON);
+    }
+
+    #[test]
+    fn test_dependencies_are_sorted_by_author_then_project() {
+        let shuffled = SAMPLE_APPLICATION_JSON
+            .replace("\"elm/core\": \"1.0.5\",", "")
+            .replacen("\"direct\": {", "\"direct\": {\"elm/core\": \"1.0.5\",", 1);
+        assert_eq!(rewrite(&shuffled), SAMPLE_APPLICATION_JSON);
+    }
+}
@@ -42,6 +42,11 @@ impl AsRef<TempDir> for Tempy {
 }
 //endregion
 
+/// Only read packages from our fixture, never the real ~/.elm.
+fn fixture_elm_home() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data/elm-home")
+}
+
 fn make_test_dir(file_type: &'static str) -> Result<Tempy> {
     let tempfile = tempfile::tempdir_in("test-data").context("Creating temp dir in test-data")?;
     fs::copy(
@@ -52,15 +57,59 @@ fn make_test_dir(file_type: &'static str) -> Result<Tempy> {
     Ok(tempfile.into())
 }
 
+/// The context every test runs commands in.
+fn context_for(tempdir: &Tempy) -> CmdContext {
+    CmdContext {
+        yes: true,
+        root: tempdir.path_buf(),
+        dry_run: false,
+        elm_home: Some(fixture_elm_home()),
+    }
+}
+
+/// A project in a git repository of its own, with its elm.json committed.
+fn project_in_repo(file_type: &'static str) -> Result<(Tempy, Repository)> {
+    let tempdir = make_test_dir(file_type)?;
+    let repo_path = pathdiff::diff_paths(tempdir.path(), std::env::current_dir()?).unwrap();
+    let repo = make_repo(&repo_path)?;
+    commit_elm_json(&repo)?;
+    Ok((tempdir, repo))
+}
+
+/// Copy the sample package into the project, without vendoring it yet.
+fn add_package(tempdir: &Tempy, dir: &str) -> Result<PathBuf> {
+    let package_dir = tempdir.path().join(dir);
+    fs::create_dir_all(package_dir.join("src"))?;
+    fs::copy(
+        "test-data/sample-elm-package.json",
+        package_dir.join("elm.json"),
+    )?;
+    Ok(package_dir)
+}
+
+/// An initialized application in its own git repository, with elm.json committed,
+/// and the sample package in vendor/elm-func-accessors, ready to be vendored.
+fn app_with_vendored_package() -> Result<(Tempy, Repository, CmdContext)> {
+    let (tempdir, repo) = project_in_repo("app")?;
+    add_package(&tempdir, "vendor/elm-func-accessors")?;
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
+    Ok((tempdir, repo, ctx))
+}
+
 fn make_repo(path: impl AsRef<Path>) -> Result<git2::Repository> {
     Ok(git2::Repository::init(path)?)
 }
 
 fn commit_elm_json(repo: &Repository) -> Result<()> {
+    commit_file(repo, Path::new("elm.json"))
+}
+
+fn commit_file(repo: &Repository, path: &Path) -> Result<()> {
     let mut index = repo.index()?;
     dbg!(repo.path());
 
-    index.add_path(Path::new("elm.json"))?;
+    index.add_path(path)?;
     index.write()?;
 
     let tree_oid = index.write_tree()?;
@@ -68,7 +117,11 @@ fn commit_elm_json(repo: &Repository) -> Result<()> {
 
     let author = git2::Signature::new("foo", "bar", &git2::Time::new(0, 0))?;
 
-    repo.commit(Some("HEAD"), &author, &author, "", &tree, &[])?;
+    // on top of whatever was committed before, if anything
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+
+    repo.commit(Some("HEAD"), &author, &author, "", &tree, &parents)?;
 
     Ok(())
 }
@@ -77,35 +130,561 @@ fn commit_elm_json(repo: &Repository) -> Result<()> {
 #[throws]
 fn test_init_pkg() {
     let tempdir = make_test_dir("package")?;
-    let ctx = CmdContext {
-        yes: true,
-        root: tempdir.path_buf(),
-    };
-    ctx.init()?;
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
 }
 
 #[test]
 #[throws]
 fn test_init_app() {
     let tempdir = make_test_dir("app")?;
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
+}
+
+#[test]
+#[throws]
+fn test_vendor() {
+    let (tempdir, _repo) = project_in_repo("app")?;
+    dbg!(tempdir.path());
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
+    let report = serde_json::to_value(ctx.vendor(None, Strategy::Newest)?)?;
+    assert_eq!(report["outcome"], "rebuilt");
+
+    let read_json = |path: &Path| -> Result<serde_json::Value> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    };
+    let original = read_json(Path::new("test-data/sample-elm-app.json"))?;
+    let vendored = read_json(&tempdir.path().join("elm.json"))?;
+    assert_eq!(original["dependencies"], vendored["dependencies"]);
+}
+
+#[test]
+#[throws]
+fn test_vendor_dir() {
+    let (tempdir, _repo, ctx) = app_with_vendored_package()?;
+    ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Newest)?;
+
+    let elm_json: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm.json"))?)?;
+    assert_eq!(
+        elm_json["source-directories"],
+        serde_json::json!(["src", "vendor/elm-func-accessors/src"])
+    );
+    let elm_vendor: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm-vendor.json"))?)?;
+    assert_eq!(
+        elm_vendor["vendored"],
+        serde_json::json!(["vendor/elm-func-accessors"])
+    );
+
+    // put elm.json back the way it was committed, so only the directory can be the problem
+    fs::copy(
+        "test-data/sample-elm-app.json",
+        tempdir.path().join("elm.json"),
+    )?;
+    fs::create_dir_all(tempdir.path().join("vendor/empty"))?;
+    assert!(ctx
+        .vendor(Some("vendor/elm-func-accessors/"), Strategy::Newest)
+        .is_err());
+    assert!(ctx.vendor(Some(".."), Strategy::Newest).is_err());
+    assert!(ctx.vendor(Some("vendor/empty"), Strategy::Newest).is_err());
+}
+
+#[test]
+#[throws]
+fn test_vendor_glob() {
+    let (tempdir, repo) = project_in_repo("app")?;
+    for package in &["vendor/b", "vendor/a"] {
+        add_package(&tempdir, package)?;
+    }
+    // not a package, so the pattern shouldn't pick it up
+    fs::create_dir_all(tempdir.path().join("vendor/notes"))?;
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
+
+    let elm_vendor_path = tempdir.path().join("elm-vendor.json");
+    let mut elm_vendor: serde_json::Value = serde_json::from_slice(&fs::read(&elm_vendor_path)?)?;
+    elm_vendor["vendored"] = serde_json::json!(["vendor/*"]);
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+    ctx.vendor(None, Strategy::Newest)?;
+
+    let elm_json: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm.json"))?)?;
+    assert_eq!(
+        elm_json["source-directories"],
+        serde_json::json!(["src", "vendor/a/src", "vendor/b/src"])
+    );
+
+    // details on the pattern hold for every package it matches
+    commit_elm_json(&repo)?;
+    elm_vendor["vendored"] = serde_json::json!([{"path": "vendor/*", "notes": "patched"}]);
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+    ctx.vendor(None, Strategy::Newest)?;
+    let elm_json: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm.json"))?)?;
+    assert_eq!(
+        elm_json["source-directories"],
+        serde_json::json!(["src", "vendor/a/src", "vendor/b/src"])
+    );
+
+    // but they can't all have the same name
+    elm_vendor["vendored"] = serde_json::json!([{"path": "vendor/*", "name": "someone/else"}]);
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+    let err = ctx.vendor(None, Strategy::Newest).unwrap_err();
+    assert!(err.to_string().contains("can't have a name"), "{:?}", err);
+}
+
+#[test]
+#[throws]
+fn test_vendored_packages_stay_inside_the_project() {
+    let tempdir = make_test_dir("app")?;
+    let project = tempdir.path().join("app");
+    fs::create_dir_all(project.join("vendor"))?;
+    fs::rename(tempdir.path().join("elm.json"), project.join("elm.json"))?;
+    add_package(&tempdir, "elsewhere")?;
     let ctx = CmdContext {
-        yes: true,
-        root: tempdir.path_buf(),
+        root: project.clone(),
+        ..context_for(&tempdir)
+    };
+    ctx.init(ConfigFormat::Json)?;
+    // nothing needs to be committed for a dry run
+    let ctx = CmdContext {
+        dry_run: true,
+        ..ctx
     };
-    ctx.init()?;
+
+    let elm_vendor_path = project.join("elm-vendor.json");
+    let mut elm_vendor: serde_json::Value = serde_json::from_slice(&fs::read(&elm_vendor_path)?)?;
+    for vendored in &["../elsewhere", "../*", "vendor/../../*"] {
+        elm_vendor["vendored"] = serde_json::json!([vendored]);
+        fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+        let err = ctx.vendor(None, Strategy::Newest).unwrap_err();
+        assert!(
+            err.to_string().contains("inside the project"),
+            "{}: {:?}",
+            vendored,
+            err
+        );
+    }
 }
 
 #[test]
 #[throws]
-fn test_vendor() {
+fn test_vendor_overrides() {
+    let (tempdir, repo, ctx) = app_with_vendored_package()?;
+
+    let elm_vendor_path = tempdir.path().join("elm-vendor.json");
+    let mut elm_vendor: serde_json::Value = serde_json::from_slice(&fs::read(&elm_vendor_path)?)?;
+    elm_vendor["vendored"] = serde_json::json!(["vendor/elm-func-accessors"]);
+    elm_vendor["overrides"] = serde_json::json!({
+        "elm/core": "1.0.5",
+        "bChiquet/elm-accessors": {
+            "version": "2.0.1",
+            "package": "vendor/elm-func-accessors"
+        },
+        // nothing asks for this, so it shouldn't show up as used
+        "elm-explorations/markdown": "1.0.0"
+    });
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+
+    let report = serde_json::to_value(ctx.vendor(None, Strategy::Newest)?)?;
+    let overrides = report["overrides"].as_array().unwrap();
+    assert_eq!(overrides.len(), 2);
+    assert_eq!(
+        overrides[0],
+        serde_json::json!({
+            "dependency": "bChiquet/elm-accessors",
+            "version": "2.0.1",
+            "instead-of": { "vendor/elm-func-accessors": "2.0.0 <= v < 3.0.0" }
+        })
+    );
+    assert_eq!(overrides[1]["dependency"], "elm/core");
+    // applies to what the vendored package's dependencies ask for, too
+    assert_eq!(
+        overrides[1]["instead-of"]["elm/browser 1.0.2"],
+        "1.0.0 <= v < 2.0.0"
+    );
+    assert_eq!(
+        overrides[1]["instead-of"]["vendor/elm-func-accessors"],
+        "1.0.0 <= v < 2.0.0"
+    );
+
+    // an override for a package that isn't vendored is pointed out, rather than quietly unused
+    elm_vendor["overrides"] = serde_json::json!({
+        "elm/json": { "version": "1.1.3", "package": "vendor/elm-typo" }
+    });
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+    commit_elm_json(&repo)?;
+    let report = ctx.vendor(None, Strategy::Newest)?;
+    assert!(report
+        .to_string()
+        .contains("elm/json is only for vendor/elm-typo, which isn't vendored"));
+    let report = serde_json::to_value(report)?;
+    assert_eq!(report["overrides"], serde_json::json!([]));
+    assert_eq!(
+        report["unused-overrides"],
+        serde_json::json!([{ "dependency": "elm/json", "package": "vendor/elm-typo" }])
+    );
+}
+
+#[test]
+#[throws]
+fn test_vendor_overrides_in_package() {
+    let (tempdir, _repo) = project_in_repo("package")?;
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
+
+    // applications that use a package won't know about its overrides, even ranges
+    let elm_vendor_path = tempdir.path().join("elm-vendor.json");
+    let mut elm_vendor: serde_json::Value = serde_json::from_slice(&fs::read(&elm_vendor_path)?)?;
+    elm_vendor["overrides"] = serde_json::json!({ "elm/core": "1.0.5 <= v < 2.0.0" });
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+    let err = ctx.vendor(None, Strategy::Newest).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("Overrides only work in applications"));
+}
+
+#[test]
+#[throws]
+fn test_lockfile() {
+    let (tempdir, repo, ctx) = app_with_vendored_package()?;
+    let module = tempdir
+        .path()
+        .join("vendor/elm-func-accessors/src/FuncAccessors.elm");
+    fs::write(&module, "module FuncAccessors exposing (..)\n")?;
+    ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Locked)?;
+
+    let lockfile: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm-vendor.lock"))?)?;
+    assert_eq!(lockfile["dependencies"]["elm/core"], "1.0.5");
+    assert_eq!(lockfile["dependencies"]["TSFoster/elm-md5"], "2.0.1");
+    assert!(lockfile["vendored"]["vendor/elm-func-accessors"]
+        .as_str()
+        .unwrap()
+        .starts_with("sha256-"));
+    ctx.check()?;
+
+    // a quiet edit to vendored code
+    fs::write(&module, "module FuncAccessors exposing (map)\n")?;
+    let outcome = ctx.check();
+    let rendered: serde_json::Value = serde_json::from_str(&OutputFormat::Json.render(&outcome))?;
+    assert_eq!(rendered["error"]["kind"], "vendored-drift");
+    assert_eq!(
+        rendered["error"]["packages"],
+        serde_json::json!(["vendor/elm-func-accessors"])
+    );
+
+    commit_elm_json(&repo)?;
+    ctx.vendor(None, Strategy::Locked)?;
+    ctx.check()?;
+}
+
+#[test]
+#[throws]
+fn test_unvendor_restores_pre_vendor_elm_json() {
+    let (tempdir, repo, ctx) = app_with_vendored_package()?;
+    // formatted differently than we'd write it, so only an exact restore gets it back
+    let original = serde_json::to_string_pretty(&serde_json::from_slice::<serde_json::Value>(
+        &fs::read("test-data/sample-elm-app.json")?,
+    )?)?;
+    fs::write(tempdir.path().join("elm.json"), &original)?;
+    commit_elm_json(&repo)?;
+    ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Newest)?;
+    assert_ne!(
+        fs::read_to_string(tempdir.path().join("elm.json"))?,
+        original
+    );
+
+    ctx.unvendor(None)?;
+    assert_eq!(
+        fs::read_to_string(tempdir.path().join("elm.json"))?,
+        original
+    );
+    let elm_vendor: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm-vendor.json"))?)?;
+    assert!(elm_vendor.get("pre-vendor-elm-json").is_none());
+}
+
+#[test]
+#[throws]
+fn test_unvendor() {
     let tempdir = make_test_dir("app")?;
-    dbg!(tempdir.path());
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
+    ctx.unvendor(None)?;
+
+    // with nothing vendored, elm.json is just the main package again
+    let sample: serde_json::Value =
+        serde_json::from_slice(&fs::read("test-data/sample-elm-app.json")?)?;
+    let elm_json: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm.json"))?)?;
+    assert_eq!(elm_json, sample);
+
+    let elm_vendor: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm-vendor.json"))?)?;
+    assert_eq!(elm_vendor["vendored"], serde_json::json!([]));
+    assert_eq!(
+        elm_vendor["main-dependencies"],
+        sample["dependencies"]["direct"]
+    );
+    assert_eq!(
+        elm_vendor["source-directories"],
+        sample["source-directories"]
+    );
+}
+
+#[test]
+#[throws]
+fn test_check() {
+    let tempdir = make_test_dir("app")?;
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Json)?;
+    ctx.check()?;
+
+    let elm_json_path = tempdir.path().join("elm.json");
+    let mut elm_json: serde_json::Value = serde_json::from_slice(&fs::read(&elm_json_path)?)?;
+    elm_json["dependencies"]["direct"]["elm/json"] = "1.1.3".into();
+    fs::write(&elm_json_path, serde_json::to_vec_pretty(&elm_json)?)?;
+
+    assert!(ctx.check().is_err());
+
+    let outcome = ctx.check();
+    let rendered: serde_json::Value = serde_json::from_str(&OutputFormat::Json.render(&outcome))?;
+    assert_eq!(rendered["ok"], false);
+    assert_eq!(rendered["error"]["kind"], "drift");
+    assert_eq!(
+        rendered["error"]["drift"]["added-dependencies"]["elm/json"],
+        "1.1.3"
+    );
+}
+
+#[test]
+#[throws]
+fn test_check_reports_indirect_drift() {
+    let (tempdir, repo, ctx) = app_with_vendored_package()?;
+    ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Locked)?;
+    commit_elm_json(&repo)?;
+    ctx.check()?;
+
+    // elm-vendor.lock says 1.1.3
+    let elm_json_path = tempdir.path().join("elm.json");
+    let mut elm_json: serde_json::Value = serde_json::from_slice(&fs::read(&elm_json_path)?)?;
+    elm_json["dependencies"]["indirect"]["elm/json"] = "1.1.2".into();
+    fs::write(&elm_json_path, serde_json::to_vec_pretty(&elm_json)?)?;
+
+    let outcome = ctx.check();
+    let rendered: serde_json::Value = serde_json::from_str(&OutputFormat::Json.render(&outcome))?;
+    assert_eq!(rendered["error"]["kind"], "drift");
+    assert_eq!(
+        rendered["error"]["drift"]["changed-indirect-dependencies"]["elm/json"],
+        serde_json::json!(["1.1.3", "1.1.2"])
+    );
+    assert!(OutputFormat::Text
+        .render(&outcome)
+        .contains("~ indirect dependency elm/json 1.1.3 -> 1.1.2"));
+}
+
+#[test]
+#[throws]
+fn test_check_accepts_older_versions_that_work() {
+    let (tempdir, repo, ctx) = app_with_vendored_package()?;
+
+    // 1.0.5 is the newest elm/core, but 1.0.2 works just as well
+    let elm_vendor_path = tempdir.path().join("elm-vendor.json");
+    let mut elm_vendor: serde_json::Value = serde_json::from_slice(&fs::read(&elm_vendor_path)?)?;
+    elm_vendor["main-dependencies"]["elm/core"] = "1.0.0 <= v < 2.0.0".into();
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+    let elm_json_path = tempdir.path().join("elm.json");
+    let mut elm_json: serde_json::Value = serde_json::from_slice(&fs::read(&elm_json_path)?)?;
+    elm_json["dependencies"]["direct"]["elm/core"] = "1.0.2".into();
+    fs::write(&elm_json_path, serde_json::to_vec_pretty(&elm_json)?)?;
+    commit_elm_json(&repo)?;
+    // without elm-vendor.lock, nothing says 1.0.2 was picked on purpose
+    assert!(ctx.check().is_err());
+
+    let elm_core = || -> Result<serde_json::Value> {
+        let elm_json: serde_json::Value = serde_json::from_slice(&fs::read(&elm_json_path)?)?;
+        Ok(elm_json["dependencies"]["direct"]["elm/core"].clone())
+    };
+    ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Locked)?;
+    assert_eq!(elm_core()?, "1.0.2");
+    // unvendoring one package doesn't upgrade everything else
+    ctx.unvendor(Some("vendor/elm-func-accessors"))?;
+    assert_eq!(elm_core()?, "1.0.2");
+    ctx.check()?;
+}
+
+#[test]
+#[throws]
+fn test_check_reports_hand_edited_versions() {
+    let (tempdir, repo, ctx) = app_with_vendored_package()?;
+    let elm_vendor_path = tempdir.path().join("elm-vendor.json");
+    let mut elm_vendor: serde_json::Value = serde_json::from_slice(&fs::read(&elm_vendor_path)?)?;
+    elm_vendor["main-dependencies"]["elm/core"] = "1.0.0 <= v < 2.0.0".into();
+    fs::write(&elm_vendor_path, serde_json::to_vec_pretty(&elm_vendor)?)?;
+    ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Locked)?;
+    commit_elm_json(&repo)?;
+    ctx.check()?;
+
+    // 1.0.2 works for every package, but vendor wouldn't have picked it
+    let elm_json_path = tempdir.path().join("elm.json");
+    let mut elm_json: serde_json::Value = serde_json::from_slice(&fs::read(&elm_json_path)?)?;
+    elm_json["dependencies"]["direct"]["elm/core"] = "1.0.2".into();
+    fs::write(&elm_json_path, serde_json::to_vec_pretty(&elm_json)?)?;
+
+    let outcome = ctx.check();
+    let rendered: serde_json::Value = serde_json::from_str(&OutputFormat::Json.render(&outcome))?;
+    assert_eq!(rendered["error"]["kind"], "drift");
+    assert_eq!(
+        rendered["error"]["drift"]["changed-dependencies"]["elm/core"],
+        serde_json::json!(["1.0.5", "1.0.2"])
+    );
+}
+
+#[test]
+#[throws]
+fn test_init_toml() {
+    let tempdir = make_test_dir("app")?;
+    let ctx = context_for(&tempdir);
+    ctx.init(ConfigFormat::Toml)?;
+    assert!(tempdir.path().join("elm-vendor.toml").is_file());
+    ctx.check()?;
+}
+
+#[test]
+#[throws]
+fn test_find_project_root() {
+    let tempdir = make_test_dir("app")?;
+    let nested = tempdir.path().join("src/Page/Home");
+    fs::create_dir_all(&nested)?;
+
+    assert_eq!(find_project_root(&nested)?, tempdir.path().canonicalize()?);
+    assert!(find_project_root(&nested.join("missing")).is_err());
+}
+
+#[test]
+#[throws]
+fn test_find_project_root_from_vendored_package() {
+    let (tempdir, _repo, _ctx) = app_with_vendored_package()?;
+    let package_dir = tempdir.path().join("vendor/elm-func-accessors");
+
+    assert_eq!(
+        find_project_root(&package_dir.join("src"))?,
+        tempdir.path().canonicalize()?
+    );
+}
+
+#[test]
+#[throws]
+fn test_vendor_in_monorepo() {
+    let tempdir = make_test_dir("app")?;
+    let frontend = tempdir.path().join("frontend");
+    fs::create_dir_all(&frontend)?;
+    fs::rename(tempdir.path().join("elm.json"), frontend.join("elm.json"))?;
     let repo_path = pathdiff::diff_paths(tempdir.path(), std::env::current_dir()?).unwrap();
     let repo = make_repo(&repo_path)?;
-    commit_elm_json(&repo)?;
+    commit_file(&repo, Path::new("frontend/elm.json"))?;
+
     let ctx = CmdContext {
-        yes: true,
-        root: tempdir.path_buf(),
+        root: find_project_root(&frontend)?,
+        ..context_for(&tempdir)
     };
-    ctx.vendor()?;
+    ctx.init(ConfigFormat::Json)?;
+    ctx.vendor(None, Strategy::Newest)?;
+    assert!(frontend.join("elm-vendor.json").is_file());
+}
+
+#[test]
+#[throws]
+fn test_vendor_dry_run() {
+    let (tempdir, _repo, ctx) = app_with_vendored_package()?;
+    let elm_json_before = fs::read(tempdir.path().join("elm.json"))?;
+    let elm_vendor_before = fs::read(tempdir.path().join("elm-vendor.json"))?;
+
+    let dry_ctx = CmdContext {
+        dry_run: true,
+        ..ctx
+    };
+    let report =
+        serde_json::to_value(dry_ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Newest)?)?;
+    assert_eq!(report["dry-run"], true);
+    assert_eq!(
+        report["changes"]["added-source-dirs"],
+        serde_json::json!(["vendor/elm-func-accessors/src"])
+    );
+
+    assert_eq!(fs::read(tempdir.path().join("elm.json"))?, elm_json_before);
+    assert_eq!(
+        fs::read(tempdir.path().join("elm-vendor.json"))?,
+        elm_vendor_before
+    );
+
+    // the diff goes to stderr, so stdout is still nothing but the report
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_elm-vendor"))
+        .env("ELM_HOME", fixture_elm_home())
+        .args(&["--format", "json", "--dry-run", "-C"])
+        .arg(tempdir.path())
+        .args(&["vendor", "vendor/elm-func-accessors"])
+        .output()?;
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["report"]["dry-run"], true);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("+        \"vendor/elm-func-accessors/src\""));
+}
+
+#[test]
+#[throws]
+fn test_vendor_from_a_subdirectory() {
+    let (tempdir, _repo, _ctx) = app_with_vendored_package()?;
+    let src = tempdir.path().join("src/Page");
+    fs::create_dir_all(&src)?;
+    let elm_vendor = || -> Result<serde_json::Value> {
+        Ok(serde_json::from_slice(&fs::read(
+            tempdir.path().join("elm-vendor.json"),
+        )?)?)
+    };
+
+    // directories are relative to where elm-vendor runs, like any other command
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_elm-vendor"))
+        .env("ELM_HOME", fixture_elm_home())
+        .current_dir(&src)
+        .args(&["--yes", "vendor", "../../vendor/elm-func-accessors"])
+        .output()?;
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        elm_vendor()?["vendored"],
+        serde_json::json!(["vendor/elm-func-accessors"])
+    );
+
+    // including from inside the vendored package itself
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_elm-vendor"))
+        .env("ELM_HOME", fixture_elm_home())
+        .args(&["--yes", "-C"])
+        .arg(tempdir.path().join("vendor/elm-func-accessors/src"))
+        .args(&["unvendor", ".."])
+        .output()?;
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(elm_vendor()?["vendored"], serde_json::json!([]));
+}
+
+#[test]
+#[throws]
+fn test_unvendor_rebuilds_when_the_main_package_changed() {
+    let (tempdir, _repo, ctx) = app_with_vendored_package()?;
+    ctx.vendor(Some("vendor/elm-func-accessors"), Strategy::Newest)?;
+
+    // a field added to elm-vendor.json after vendoring, which the old elm.json doesn't have
+    let elm_vendor_path = tempdir.path().join("elm-vendor.json");
+    let mut elm_vendor: serde_json::Value = serde_json::from_slice(&fs::read(&elm_vendor_path)?)?;
+    elm_vendor["extras"]["notes"] = "ours".into();
+    fs::write(&elm_vendor_path, serde_json::to_string_pretty(&elm_vendor)?)?;
+
+    ctx.unvendor(None)?;
+    let elm_json: serde_json::Value =
+        serde_json::from_slice(&fs::read(tempdir.path().join("elm.json"))?)?;
+    assert_eq!(elm_json["notes"], "ours");
 }